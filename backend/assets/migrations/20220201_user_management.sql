@@ -0,0 +1 @@
+ALTER TABLE user ADD disabled INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS api_token (
+    token_id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_used_at INTEGER NOT NULL DEFAULT 0,
+    expire_at INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS session (
+    session_id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    device TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_used_at INTEGER NOT NULL,
+    expire_at INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+ALTER TABLE user ADD totp_secret TEXT NOT NULL DEFAULT '';
+ALTER TABLE user ADD totp_enabled INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE user ADD totp_last_step INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE site ADD require_admin_2fa INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS recovery_code (
+    code_id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS acl (
+    acl_id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    subject_type TEXT NOT NULL,
+    subject_id INTEGER NOT NULL,
+    can_read INTEGER NOT NULL DEFAULT 0,
+    can_write INTEGER NOT NULL DEFAULT 0,
+    can_delete INTEGER NOT NULL DEFAULT 0,
+    can_share INTEGER NOT NULL DEFAULT 0,
+    UNIQUE(path, subject_type, subject_id)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS user_group (
+    group_id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS group_member (
+    group_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY (group_id, user_id)
+);
+
+ALTER TABLE hidden ADD group_id INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE user ADD home_dir TEXT NOT NULL DEFAULT '';
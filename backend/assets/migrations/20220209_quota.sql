@@ -0,0 +1,14 @@
+ALTER TABLE user ADD quota_bytes INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS dir_quota (
+    quota_id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    max_bytes INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS uploaded_file (
+    path TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
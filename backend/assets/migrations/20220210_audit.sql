@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS audit (
+    audit_id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    ip TEXT NOT NULL,
+    action TEXT NOT NULL,
+    path TEXT NOT NULL,
+    target TEXT NOT NULL,
+    result TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_created_at ON audit (created_at);
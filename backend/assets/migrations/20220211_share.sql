@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS share (
+    share_id INTEGER PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    path TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    password TEXT NOT NULL DEFAULT '',
+    max_downloads INTEGER NOT NULL DEFAULT 0,
+    download_count INTEGER NOT NULL DEFAULT 0,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    expire_at INTEGER NOT NULL DEFAULT 0
+);
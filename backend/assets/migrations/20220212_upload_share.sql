@@ -0,0 +1,4 @@
+ALTER TABLE share ADD COLUMN share_type TEXT NOT NULL DEFAULT 'download';
+ALTER TABLE share ADD COLUMN max_file_size INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE share ADD COLUMN max_files INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE share ADD COLUMN upload_count INTEGER NOT NULL DEFAULT 0;
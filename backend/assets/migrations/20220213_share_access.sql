@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS share_access (
+    access_id INTEGER PRIMARY KEY,
+    share_id INTEGER NOT NULL,
+    ip TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    path TEXT NOT NULL,
+    range_start INTEGER NOT NULL,
+    range_end INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    completed INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS share_access_share_id ON share_access (share_id);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS upload_task (
+    uuid TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    share_id INTEGER NOT NULL DEFAULT 0,
+    filename TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    dir TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    finished_slices INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE upload_task ADD COLUMN conflict TEXT NOT NULL DEFAULT 'overwrite';
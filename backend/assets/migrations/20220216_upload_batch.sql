@@ -0,0 +1 @@
+ALTER TABLE upload_task ADD COLUMN batch_id TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS job (
+    uuid TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    source TEXT NOT NULL,
+    target TEXT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT NOT NULL DEFAULT '',
+    created_at INTEGER NOT NULL,
+    finished_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS job_user_id ON job (user_id, finished_at);
@@ -0,0 +1,96 @@
+use crate::entity::acl::{Acl, AclSubject};
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use crate::entity::group::Group;
+use crate::entity::request::AclRequest;
+use crate::entity::user::User;
+use crate::service::app_state::AppState;
+use crate::service::audit::Auditor;
+use crate::service::auth::AuthAdmin;
+use crate::util;
+use rocket::serde::json::Json;
+use rocket::{Route, State};
+use sqlx::Connection;
+use std::path::PathBuf;
+
+pub fn route() -> Vec<Route> {
+    routes![list_acl, update_acl, delete_acl]
+}
+
+#[get("/acl?<path>")]
+async fn list_acl(
+    state: &State<AppState>,
+    path: Option<&str>,
+    _admin: AuthAdmin,
+) -> Result<Json<Vec<Acl>>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let acls = match path {
+        Some(p) => Acl::find_by_path(p, &mut conn).await?,
+        None => Acl::find_all(&mut conn).await?,
+    };
+
+    Ok(Json(acls))
+}
+
+// Create the entry, or replace the existing one for the same subject on the same path.
+#[put("/acl", data = "<req_body>")]
+async fn update_acl(
+    state: &State<AppState>,
+    req_body: Json<AclRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    let subject = format!("{}:{}", req_body.subject_type.as_str(), req_body.subject_id);
+    audit.log(admin.uid, AuditAction::UpdateAcl, &req_body.path, &subject);
+
+    let storage = state.get_site()?.storage.clone();
+    let path = util::parse_encoded_url(&req_body.path)?;
+    if !PathBuf::from(&storage).join(&path).exists() {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    match req_body.subject_type {
+        AclSubject::User => {
+            if User::find_user_by_id(req_body.subject_id, &mut conn)
+                .await?
+                .is_none()
+            {
+                return Err(Error::NotFound);
+            }
+        }
+        AclSubject::Group => {
+            if Group::find_by_id(req_body.subject_id, &mut conn)
+                .await?
+                .is_none()
+            {
+                return Err(Error::NotFound);
+            }
+        }
+    }
+
+    let path_str = path.to_str().ok_or(Error::BadRequest)?;
+    let acl = Acl::from_req(path_str, &req_body);
+    let mut tx = conn.begin().await?;
+    acl.upsert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[delete("/acl/<acl_id>")]
+async fn delete_acl(
+    state: &State<AppState>,
+    acl_id: i64,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::DeleteAcl, "", &acl_id.to_string());
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    Acl::delete_query(acl_id, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
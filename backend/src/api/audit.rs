@@ -0,0 +1,78 @@
+use crate::entity::audit::Audit;
+use crate::entity::error::Error;
+use crate::entity::request::AuditFilter;
+use crate::entity::response::AuditPageResponse;
+use crate::service::app_state::AppState;
+use crate::service::auth::AuthAdmin;
+use rocket::http::ContentType;
+use rocket::serde::json::{self, Json};
+use rocket::{Route, State};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+pub fn route() -> Vec<Route> {
+    routes![list_audit, export_audit]
+}
+
+#[get("/audit?<page>&<page_size>&<filter..>")]
+async fn list_audit(
+    state: &State<AppState>,
+    _admin: AuthAdmin,
+    page: Option<i64>,
+    page_size: Option<i64>,
+    filter: AuditFilter,
+) -> Result<Json<AuditPageResponse>, Error> {
+    let page = page.unwrap_or(1).max(1);
+    let page_size = page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let mut conn = state.get_pool_conn().await?;
+    let total = Audit::count(&filter, &mut conn).await?;
+    let entries = Audit::find(&filter, page_size, (page - 1) * page_size, &mut conn).await?;
+
+    Ok(Json(AuditPageResponse {
+        total,
+        page,
+        page_size,
+        entries,
+    }))
+}
+
+#[get("/audit/export?<format>&<filter..>")]
+async fn export_audit(
+    state: &State<AppState>,
+    _admin: AuthAdmin,
+    format: Option<&str>,
+    filter: AuditFilter,
+) -> Result<(ContentType, String), Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let entries = Audit::find(&filter, 0, 0, &mut conn).await?;
+
+    match format.unwrap_or("csv") {
+        "csv" => {
+            let mut body = String::from(Audit::csv_header());
+            body.push('\n');
+            for entry in entries.iter() {
+                body.push_str(&entry.to_csv_row());
+                body.push('\n');
+            }
+
+            Ok((ContentType::CSV, body))
+        }
+        "jsonl" => {
+            let mut body = String::new();
+            for entry in entries.iter() {
+                body.push_str(&json::serde_json::to_string(entry).map_err(|e| {
+                    eprintln!("{}", e);
+                    500
+                })?);
+                body.push('\n');
+            }
+
+            Ok((ContentType::new("application", "x-ndjson"), body))
+        }
+        _ => Err(Error::BadRequest),
+    }
+}
@@ -0,0 +1,138 @@
+use crate::entity::acl::Access;
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use crate::entity::fetch_task::FetchTask;
+use crate::entity::request::FetchRequest;
+use crate::entity::upload_task::ConflictPolicy;
+use crate::service::access::AccessControl;
+use crate::service::app_state::AppState;
+use crate::service::audit::{self, Auditor};
+use crate::service::auth::{AuthAdmin, AuthUser};
+use crate::util;
+use rocket::serde::json::Json;
+use rocket::{Route, State};
+
+pub fn route() -> Vec<Route> {
+    routes![start_fetch, list_fetches, get_fetch_status, cancel_fetch]
+}
+
+#[post("/fetch", data = "<req_body>")]
+async fn start_fetch(
+    state: &State<AppState>,
+    req_body: Json<FetchRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<String, Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::Fetch,
+        &req_body.url,
+        &audit::decode_path(&req_body.target),
+    );
+
+    let url = req_body.url.trim();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(Error::BadRequest);
+    }
+
+    let user = AuthUser::from(admin);
+    let access = AccessControl::load(state, &user).await?;
+    let target_dir = access.resolve(&req_body.target).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+    if !target_dir.is_dir() {
+        return Err(Error::NotFound);
+    }
+
+    if !access.can(&target_dir, Access::Write) {
+        return Err(Error::Forbidden);
+    }
+
+    let filename = match req_body.filename.as_deref() {
+        Some(filename) if !filename.is_empty() => filename.to_owned(),
+        _ => filename_from_url(url),
+    };
+    if !util::is_valid_filename(&filename) {
+        return Err(Error::BadRequest);
+    }
+
+    // Fail early rather than after the whole file is downloaded.
+    let conflict = req_body.conflict.unwrap_or(ConflictPolicy::Fail);
+    if conflict == ConflictPolicy::Fail || conflict == ConflictPolicy::Overwrite {
+        conflict.resolve(&target_dir, &filename)?;
+    }
+
+    let storage = state.get_site()?.storage.clone();
+    let available_space = util::file_system::get_available_space(&storage);
+    let max_size = match (req_body.max_size.unwrap_or(0), available_space) {
+        (0, space) => space,
+        (size, 0) => size,
+        (size, space) => size.min(space),
+    };
+
+    let task = FetchTask::new(url, target_dir, &filename, user.uid, conflict, max_size);
+    let uuid = task.uuid.clone();
+    state.fetches.submit(state.pool.clone(), storage, task);
+
+    Ok(uuid)
+}
+
+#[get("/fetch")]
+async fn list_fetches(state: &State<AppState>, _admin: AuthAdmin) -> Json<Vec<FetchTask>> {
+    Json(state.fetches.get_all())
+}
+
+#[get("/fetch/<uuid>")]
+async fn get_fetch_status(
+    state: &State<AppState>,
+    uuid: &str,
+    _admin: AuthAdmin,
+) -> Result<Json<FetchTask>, Error> {
+    let task = state.fetches.get(uuid).ok_or(Error::NotFound)?;
+
+    Ok(Json(task))
+}
+
+#[delete("/fetch/<uuid>")]
+async fn cancel_fetch(
+    state: &State<AppState>,
+    uuid: &str,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::CancelFetch, uuid, "");
+
+    state.fetches.get(uuid).ok_or(Error::NotFound)?;
+    state.fetches.cancel(uuid);
+
+    Ok(())
+}
+
+// The last segment of the url path, e.g. "a b.zip" for "https://host/files/a%20b.zip?x=1".
+fn filename_from_url(url: &str) -> String {
+    let path = url.split(&['?', '#'][..]).next().unwrap_or("");
+    let path = path.splitn(4, '/').nth(3).unwrap_or("");
+    let segment = path.rsplit('/').next().unwrap_or("");
+
+    match urlencoding::decode(segment) {
+        Ok(name) if !name.is_empty() => name.into_owned(),
+        _ => "download".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filename_from_url() {
+        assert_eq!(
+            filename_from_url("https://host/files/a%20b.zip?x=1"),
+            "a b.zip"
+        );
+        assert_eq!(filename_from_url("http://host/a.txt#top"), "a.txt");
+        assert_eq!(filename_from_url("http://host/dir/"), "download");
+        assert_eq!(filename_from_url("http://host"), "download");
+    }
+}
@@ -1,26 +1,34 @@
-use crate::entity::copy_move_task::{CopyMoveFileRequest, CopyMoveTask};
+use crate::entity::acl::{Access, Acl};
+use crate::entity::audit::AuditAction;
+use crate::entity::copy_move_task::{CopyMoveFileRequest, CopyMoveTask, CopyMoveTaskStatus};
 use crate::entity::error::Error;
 use crate::entity::file::{File, FileType};
+use crate::entity::group::Group;
 use crate::entity::hidden::Hidden;
-use crate::entity::request::{
-    CreateDirRequest, GenerateLinkRequest, RenameFileRequest, SetFileVisibilityRequest,
-};
-use crate::entity::response::FileResponse;
+use crate::entity::job::Job;
+use crate::entity::quota::{DirQuota, UploadedFile};
+use crate::entity::request::{CreateDirRequest, RenameFileRequest, SetFileVisibilityRequest};
+use crate::entity::response::{FileResponse, JobResponse};
+use crate::entity::share::Share;
+use crate::entity::share_access::ShareAccess;
+use crate::entity::user::User;
+use crate::service::access::AccessControl;
 use crate::service::app_state::AppState;
+use crate::service::audit::{self, Auditor};
 use crate::service::auth::{AuthAdmin, AuthUser};
+use crate::service::quota;
 use crate::service::range::{Range, RangedFile};
 use crate::service::track;
-use crate::util::constants::ZIP_BUFFER_SIZE;
+use crate::util::constants::{JOB_HISTORY_LIMIT, JOB_MAX_PER_USER, ZIP_BUFFER_SIZE};
 use crate::util::{self, file_system};
 use anyhow::Result as AnyResult;
-use async_zip::{write::ZipFileWriter, Compression, ZipEntryBuilder};
 use rocket::fs::NamedFile;
 use rocket::response::stream::ByteStream;
 use rocket::serde::json::Json;
 use rocket::tokio::fs;
-use rocket::tokio::io::{self, AsyncReadExt, AsyncWrite};
+use rocket::tokio::io::AsyncReadExt;
 use rocket::{Route, State};
-use sqlx::Acquire;
+use sqlx::{Acquire, Pool, Sqlite};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
@@ -32,12 +40,14 @@ pub fn route() -> Vec<Route> {
         delete_file,
         file_content,
         video_track,
-        generate_share_link,
-        get_share_link,
         search_files,
         update_file_visibility,
         copy_move_file,
         get_copy_move_status,
+        list_jobs,
+        pause_job,
+        resume_job,
+        cancel_job,
         download_dir
     ]
 }
@@ -49,9 +59,13 @@ async fn dir_content(
     state: &State<AppState>,
 ) -> Result<Json<Vec<File>>, Error> {
     let storage = state.get_site()?.storage.clone();
+    let access = AccessControl::load(state, &user).await?;
     let target_path = match path {
-        Some(dir) => PathBuf::from(&storage).join(&util::parse_encoded_url(dir)?),
-        None => PathBuf::from(&storage),
+        Some(dir) => access.resolve(dir).map_err(|e| {
+            eprintln!("{}", e);
+            400
+        })?,
+        None => access.root().to_path_buf(),
     };
 
     if !target_path.exists() || !target_path.is_dir() {
@@ -59,20 +73,25 @@ async fn dir_content(
         return Err(Error::BadRequest);
     }
 
-    let mut conn = state.get_pool_conn().await?;
-    let hiddens = Hidden::find_all(&mut conn).await?;
-
-    if get_least_permission(&target_path, &storage, &hiddens) > user.permission {
+    if !access.can(&target_path, Access::Read) {
         return Err(Error::Unauthorized);
     }
 
+    let mut conn = state.get_pool_conn().await?;
+    let hiddens = Hidden::find_all(&mut conn).await?;
+
     let mut dir_iterator = fs::read_dir(target_path).await?;
     let mut content: Vec<File> = Vec::new();
     while let Some(entry) = dir_iterator.next_entry().await? {
         let path = entry.path();
-        let least_permission = get_least_permission(&path, &storage, &hiddens);
-        if least_permission <= user.permission {
-            content.push(File::from_path(&path, false, &storage, least_permission)?);
+        if access.can(&path, Access::Read) {
+            let least_permission = get_least_permission(&path, &storage, &hiddens);
+            content.push(File::from_path(
+                &path,
+                false,
+                access.root(),
+                least_permission,
+            )?);
         }
     }
 
@@ -82,18 +101,28 @@ async fn dir_content(
 #[get("/download/dir?<path>")]
 async fn download_dir(
     path: &str,
-    _user: AuthAdmin,
+    user: AuthUser,
     state: &State<AppState>,
-) -> ByteStream![Vec<u8>] {
+) -> Result<ByteStream![Vec<u8>], Error> {
+    let access = AccessControl::load(state, &user).await?;
+    let target_path = get_target_path(&access, path).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+
+    if !target_path.is_dir() || !access.can(&target_path, Access::Read) {
+        return Err(Error::Unauthorized);
+    }
+
     let (mut writer, mut reader) = tokio::io::duplex(ZIP_BUFFER_SIZE);
-    let target_path = get_target_path(state, path).unwrap();
     rocket::tokio::spawn(async move {
-        if let Err(e) = zip_dir(&mut writer, &target_path).await {
+        let readable = |p: &Path| access.can(p, Access::Read);
+        if let Err(e) = file_system::zip_dir(&mut writer, &target_path, readable).await {
             println!("Error ziping dir: {}", e);
         }
     });
 
-    ByteStream! {
+    Ok(ByteStream! {
         loop {
             let mut buf = vec![0; ZIP_BUFFER_SIZE];
             let r = reader.read(&mut buf).await.unwrap();
@@ -103,24 +132,38 @@ async fn download_dir(
             buf.truncate(r);
             yield buf;
         }
-    }
+    })
 }
 
 #[post("/dir", data = "<req_body>")]
 async fn create_dir(
     state: &State<AppState>,
     req_body: Json<CreateDirRequest>,
-    _user: AuthAdmin,
+    user: AuthUser,
+    audit: Auditor<'_>,
 ) -> Result<(), Error> {
-    let storage = state.get_site()?.storage.clone();
-    let parent = util::parse_encoded_url(&req_body.parent)?;
-    let parent_path = PathBuf::from(storage).join(&parent);
+    audit.log(
+        user.uid,
+        AuditAction::CreateDir,
+        &req_body.parent,
+        &req_body.name,
+    );
+
+    let access = AccessControl::load(state, &user).await?;
+    let parent_path = access.resolve(&req_body.parent).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
     let target_path = parent_path.join(&req_body.name);
 
-    if !parent_path.exists() || target_path.exists() {
+    if !util::is_valid_filename(&req_body.name) || !parent_path.exists() || target_path.exists() {
         return Err(Error::BadRequest);
     }
 
+    if !access.can(&parent_path, Access::Write) {
+        return Err(Error::Forbidden);
+    }
+
     Ok(fs::create_dir(target_path).await?)
 }
 
@@ -131,7 +174,8 @@ async fn file_content(
     state: &State<AppState>,
     range_header: Range,
 ) -> Result<FileResponse, Error> {
-    let target_path = get_target_path(state, path).map_err(|e| {
+    let access = AccessControl::load(state, &user).await?;
+    let target_path = get_target_path(&access, path).map_err(|e| {
         eprintln!("{}", e);
         return 400;
     })?;
@@ -140,10 +184,7 @@ async fn file_content(
         return Err(Error::BadRequest);
     }
 
-    let mut conn = state.get_pool_conn().await?;
-    let hiddens = Hidden::find_all(&mut conn).await?;
-    let storage = state.get_site()?.storage.clone();
-    if max_permission_parent(&target_path, &storage, &hiddens) > user.permission {
+    if !access.can(&target_path, Access::Read) {
         return Err(Error::Unauthorized);
     }
 
@@ -166,26 +207,39 @@ async fn update_file_name(
     state: &State<AppState>,
     path: &str,
     req_body: Json<RenameFileRequest>,
-    _user: AuthAdmin,
+    user: AuthUser,
+    audit: Auditor<'_>,
 ) -> Result<(), Error> {
-    let current_file = get_target_path(state, path).map_err(|e| {
+    audit.log(user.uid, AuditAction::Rename, path, &req_body.new_name);
+
+    let access = AccessControl::load(state, &user).await?;
+    let current_file = get_target_path(&access, path).map_err(|e| {
         eprintln!("{}", e);
         return 400;
     })?;
 
+    if current_file == access.root() || !access.can(&current_file, Access::Write) {
+        return Err(Error::Forbidden);
+    }
+
     let target_file = current_file.parent().unwrap().join(&req_body.new_name);
 
-    if target_file.exists() {
+    if !util::is_valid_filename(&req_body.new_name) || target_file.exists() {
         return Err(Error::BadRequest);
     }
 
-    fs::rename(current_file, &target_file).await?;
+    fs::rename(&current_file, &target_file).await?;
 
-    let target_relative_path = get_relative_path(state, &target_file)?;
-    let target_path_str = target_relative_path.to_str().unwrap();
+    let current_path_str = access.storage_relative(&current_file)?;
+    let target_path_str = access.storage_relative(&target_file)?;
     let mut conn = state.get_pool_conn().await?;
     let mut tx = conn.begin().await?;
-    Hidden::update_all_sub_path_query(&mut tx, path, target_path_str).await?;
+    Hidden::update_all_sub_path_query(&mut tx, &current_path_str, &target_path_str).await?;
+    Acl::update_all_sub_path_query(&mut tx, &current_path_str, &target_path_str).await?;
+    DirQuota::update_all_sub_path_query(&mut tx, &current_path_str, &target_path_str).await?;
+    UploadedFile::update_all_sub_path_query(&mut tx, &current_path_str, &target_path_str).await?;
+    Share::update_all_sub_path_query(&mut tx, &current_path_str, &target_path_str).await?;
+    User::update_home_sub_path_query(&mut tx, &current_path_str, &target_path_str).await?;
     tx.commit().await?;
 
     Ok(())
@@ -196,16 +250,38 @@ async fn update_file_visibility(
     state: &State<AppState>,
     path: &str,
     req_body: Json<SetFileVisibilityRequest>,
-    _user: AuthAdmin,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
 ) -> Result<(), Error> {
+    let visibility = if req_body.visible {
+        "visible"
+    } else {
+        "hidden"
+    };
+    audit.log(admin.uid, AuditAction::SetVisibility, path, visibility);
+
+    let access = AccessControl::load(state, &admin.into()).await?;
+    let target_path = get_target_path(&access, path).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+    let path_str = access.storage_relative(&target_path)?;
+
     let mut conn = state.get_pool_conn().await?;
+    let group_id = req_body.group_id.unwrap_or(0);
+    if group_id > 0 && Group::find_by_id(group_id, &mut conn).await?.is_none() {
+        return Err(Error::NotFound);
+    }
+
     let mut tx = conn.begin().await?;
 
+    // A path visible to a group is hidden from all the other users except admins.
     if !req_body.visible {
-        let hidden = Hidden::new(path, 1);
+        let least_permission = if group_id > 0 { 9 } else { 1 };
+        let hidden = Hidden::new(&path_str, least_permission, group_id);
         hidden.insert_query(&mut tx).await?;
     } else {
-        Hidden::delete_query(path, &mut tx).await?;
+        Hidden::delete_query(&path_str, &mut tx).await?;
     }
 
     tx.commit().await?;
@@ -214,12 +290,26 @@ async fn update_file_visibility(
 }
 
 #[delete("/file/<path>")]
-async fn delete_file(state: &State<AppState>, path: &str, _user: AuthAdmin) -> Result<(), Error> {
-    let target_path = get_target_path(state, path).map_err(|e| {
+async fn delete_file(
+    state: &State<AppState>,
+    path: &str,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(user.uid, AuditAction::Delete, path, "");
+
+    let access = AccessControl::load(state, &user).await?;
+    let target_path = get_target_path(&access, path).map_err(|e| {
         eprintln!("{}", e);
         return 400;
     })?;
 
+    if target_path == access.root() || !access.can(&target_path, Access::Delete) {
+        return Err(Error::Forbidden);
+    }
+
+    let path_str = access.storage_relative(&target_path)?;
+
     if target_path.is_file() {
         fs::remove_file(target_path).await?;
     } else {
@@ -228,7 +318,12 @@ async fn delete_file(state: &State<AppState>, path: &str, _user: AuthAdmin) -> R
 
     let mut conn = state.get_pool_conn().await?;
     let mut tx = conn.begin().await?;
-    Hidden::delete_all_sub_path_query(&mut tx, path).await?;
+    Hidden::delete_all_sub_path_query(&mut tx, &path_str).await?;
+    Acl::delete_all_sub_path_query(&mut tx, &path_str).await?;
+    DirQuota::delete_all_sub_path_query(&mut tx, &path_str).await?;
+    UploadedFile::delete_all_sub_path_query(&mut tx, &path_str).await?;
+    Share::delete_all_sub_path_query(&mut tx, &path_str).await?;
+    ShareAccess::delete_orphans_query(&mut tx).await?;
     tx.commit().await?;
 
     Ok(())
@@ -237,13 +332,13 @@ async fn delete_file(state: &State<AppState>, path: &str, _user: AuthAdmin) -> R
 // Temporary solution for track file searching.
 // Could remove this function in the future.
 #[get("/file/track/<path>")]
-async fn video_track(
-    path: &str,
-    _user: AuthUser,
-    state: &State<AppState>,
-) -> Result<String, Error> {
-    let storage = state.get_site()?.storage.clone();
-    let target_path = PathBuf::from(storage).join(&util::parse_encoded_url(path)?);
+async fn video_track(path: &str, user: AuthUser, state: &State<AppState>) -> Result<String, Error> {
+    let access = AccessControl::load(state, &user).await?;
+    let target_path = access.resolve(path)?;
+
+    if !access.can(&target_path, Access::Read) {
+        return Err(Error::Unauthorized);
+    }
 
     let track_str = match track::get_track(target_path).await {
         Ok(str) => str,
@@ -256,54 +351,6 @@ async fn video_track(
     Ok(track_str)
 }
 
-#[post("/file/share", data = "<req_body>")]
-async fn generate_share_link(
-    state: &State<AppState>,
-    req_body: Json<GenerateLinkRequest>,
-    _user: AuthUser,
-) -> Result<String, Error> {
-    let secret = state.get_secret()?;
-    let path_encode = urlencoding::encode(&req_body.path);
-    let input = format!("expire={}&path={}", req_body.expire, path_encode);
-    let hash = util::sha256(&input, &secret);
-
-    Ok(format!("hash={}&{}", hash, input))
-}
-
-#[get("/file/share?<path>&<expire>&<hash>")]
-async fn get_share_link(
-    state: &State<AppState>,
-    path: &str,
-    expire: i64,
-    hash: &str,
-    range_header: Range,
-) -> Result<FileResponse, Error> {
-    let secret = state.get_secret()?;
-    let path_encode = urlencoding::encode(path);
-
-    let input = format!("expire={}&path={}", expire, path_encode);
-    if hash != util::sha256(&input, &secret) {
-        return Err(Error::BadRequest);
-    }
-
-    let target_path = get_target_path(state, path).map_err(|e| {
-        eprintln!("{}", e);
-        return 400;
-    })?;
-
-    if !target_path.is_file() {
-        return Err(Error::BadRequest);
-    }
-
-    match range_header.range {
-        Some(range) => {
-            let ranged_file = RangedFile::new(range, target_path).await?;
-            Ok(FileResponse::Range(ranged_file))
-        }
-        None => Ok(FileResponse::Binary(NamedFile::open(target_path).await?)),
-    }
-}
-
 #[get("/file/search?<keywords>")]
 async fn search_files(
     state: &State<AppState>,
@@ -313,7 +360,8 @@ async fn search_files(
     let decoded_keywords = util::parse_encoded_url(keywords)?;
     let decoded_keywords_str = decoded_keywords.as_os_str().to_str().unwrap();
     let keywords_splits: Vec<&str> = decoded_keywords_str.split("+").collect();
-    let results = search_dir_all(state, &keywords_splits, user.permission).await?;
+    let access = AccessControl::load(state, &user).await?;
+    let results = search_dir_all(&keywords_splits, &access)?;
 
     Ok(Json(results))
 }
@@ -321,82 +369,291 @@ async fn search_files(
 #[post("/file/copy-move", data = "<req_body>")]
 async fn copy_move_file(
     state: &State<AppState>,
-    admin: AuthAdmin,
+    user: AuthUser,
     req_body: Json<CopyMoveFileRequest>,
+    audit: Auditor<'_>,
 ) -> Result<String, Error> {
-    if !CopyMoveTask::allow_new_task() {
-        eprintln!("Copy or move task is already running.");
-        return Err(Error::BadRequest);
+    let action = if req_body.is_copy {
+        AuditAction::Copy
+    } else {
+        AuditAction::Move
+    };
+    audit.log(
+        user.uid,
+        action,
+        &req_body.source,
+        &audit::decode_path(&req_body.target),
+    );
+
+    if state.jobs.find_user_tasks(user.uid).len() >= JOB_MAX_PER_USER {
+        return Err(Error::TooManyRequests);
+    }
+
+    let access = AccessControl::load(state, &user).await?;
+    let source = get_target_path(&access, &req_body.source)?;
+    let target = get_target_path(&access, &req_body.target)?;
+    if !access.can(&source, Access::Read)
+        || !access.can(&target, Access::Write)
+        || (!req_body.is_copy && !access.can(&source, Access::Delete))
+    {
+        return Err(Error::Forbidden);
+    }
+
+    let storage = state.get_site()?.storage.clone();
+    let moved_from = if req_body.is_copy {
+        None
+    } else {
+        Some(source.as_path())
+    };
+    let mut conn = state.get_pool_conn().await?;
+    let size = quota::dir_size(&source).await?;
+    if quota::exceeds_quota(
+        Path::new(&storage),
+        user.uid,
+        &target,
+        size,
+        moved_from,
+        &mut conn,
+    )
+    .await?
+    {
+        return Err(Error::InsufficientStorage);
     }
 
-    let source = get_target_path(state, &req_body.source)?;
-    let target = get_target_path(state, &req_body.target)?;
     let task = CopyMoveTask::new(
         source,
         target,
-        admin.uid,
+        user.uid,
         req_body.is_copy,
         req_body.overwrite,
     );
-    task.set_static_value();
-    task.run();
+    task.dest().map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+
+    // The task fails on them anyway, but only after waiting in the queue.
+    if !task.is_copy && !task.overwrite {
+        let counted = task.clone();
+        let existing = rocket::tokio::task::spawn_blocking(move || counted.count_existing())
+            .await
+            .map_err(|e| {
+                eprintln!("{}", e);
+                500
+            })??;
+        if existing > 0 {
+            return Err(Error::Conflict);
+        }
+    }
+
+    let handle = state
+        .jobs
+        .submit(task.clone(), state.pool.clone(), storage.clone());
+
+    // Keep the owners of the files up to date for the user quota once the task is done.
+    let pool = state.pool.clone();
+    let uuid = task.uuid.clone();
+    rocket::tokio::spawn(async move {
+        if let Ok(true) = handle.await {
+            if let Err(e) = update_file_owners(&pool, &storage, &task).await {
+                eprintln!("Error updating file owners: {}", e);
+            }
+        }
+    });
 
-    Ok(task.uuid.clone())
+    Ok(uuid)
 }
 
+// The task is looked up in the history once finished. The tasks of other users are
+// not found.
 #[get("/file/copy-move-status/<uuid>")]
-async fn get_copy_move_status(uuid: String, admin: AuthAdmin) -> Result<Json<CopyMoveTask>, Error> {
-    let task = CopyMoveTask::get_static_value();
-    if task.is_none() || task.as_ref().unwrap().uuid != uuid {
-        return Err(Error::NotFound);
+async fn get_copy_move_status(
+    state: &State<AppState>,
+    uuid: &str,
+    user: AuthUser,
+) -> Result<Json<JobResponse>, Error> {
+    let job = match state.jobs.get(uuid) {
+        Some(task) => {
+            let storage = state.get_site()?.storage.clone();
+            Some((
+                task.user_id,
+                JobResponse::from_task(task, Path::new(&storage)),
+            ))
+        }
+        None => {
+            let mut conn = state.get_pool_conn().await?;
+            Job::find_by_uuid(uuid, &mut conn)
+                .await?
+                .map(|job| (job.user_id, JobResponse::from(job)))
+        }
+    };
+
+    match job {
+        Some((user_id, job)) if user_id == user.uid => Ok(Json(job)),
+        _ => Err(Error::NotFound),
     }
+}
 
-    if task.as_ref().unwrap().user_id != admin.uid {
-        return Err(Error::BadRequest);
+// The queued and running tasks of the user, followed by the latest finished ones.
+#[get("/jobs")]
+async fn list_jobs(
+    state: &State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<JobResponse>>, Error> {
+    let storage = state.get_site()?.storage.clone();
+    let mut jobs: Vec<JobResponse> = state
+        .jobs
+        .find_user_tasks(user.uid)
+        .into_iter()
+        .map(|task| JobResponse::from_task(task, Path::new(&storage)))
+        .collect();
+
+    let mut conn = state.get_pool_conn().await?;
+    for job in Job::find_user_jobs(user.uid, JOB_HISTORY_LIMIT, &mut conn).await? {
+        jobs.push(job.into());
     }
 
-    let mut task_res = task.unwrap();
-    task_res.user_id = 0;
+    Ok(Json(jobs))
+}
 
-    Ok(Json(task_res))
+// Only a running task can be paused, while a queued one can still be cancelled.
+#[put("/jobs/<uuid>/pause")]
+async fn pause_job(state: &State<AppState>, uuid: &str, user: AuthUser) -> Result<(), Error> {
+    change_job_status(
+        state,
+        uuid,
+        &user,
+        &[CopyMoveTaskStatus::InProgress],
+        CopyMoveTaskStatus::Paused,
+    )
 }
 
-fn get_target_path(state: &State<AppState>, path: &str) -> AnyResult<PathBuf> {
-    let storage = state.get_site()?.storage.clone();
-    let target_path = PathBuf::from(&storage).join(&util::parse_encoded_url(path)?);
+#[put("/jobs/<uuid>/resume")]
+async fn resume_job(state: &State<AppState>, uuid: &str, user: AuthUser) -> Result<(), Error> {
+    change_job_status(
+        state,
+        uuid,
+        &user,
+        &[CopyMoveTaskStatus::Paused],
+        CopyMoveTaskStatus::InProgress,
+    )
+}
 
-    if !target_path.exists() {
-        return Err(anyhow::anyhow!("Invalid path: {:?}", target_path));
+#[delete("/jobs/<uuid>")]
+async fn cancel_job(
+    state: &State<AppState>,
+    uuid: &str,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(user.uid, AuditAction::CancelJob, uuid, "");
+
+    change_job_status(
+        state,
+        uuid,
+        &user,
+        &[
+            CopyMoveTaskStatus::Pending,
+            CopyMoveTaskStatus::InProgress,
+            CopyMoveTaskStatus::Paused,
+        ],
+        CopyMoveTaskStatus::Cancelled,
+    )
+}
+
+fn change_job_status(
+    state: &AppState,
+    uuid: &str,
+    user: &AuthUser,
+    from: &[CopyMoveTaskStatus],
+    to: CopyMoveTaskStatus,
+) -> Result<(), Error> {
+    match state.jobs.get(uuid) {
+        Some(task) if task.user_id == user.uid => {}
+        _ => return Err(Error::NotFound),
     }
 
-    Ok(target_path)
+    if !state.jobs.transition(uuid, from, to) {
+        return Err(Error::Conflict);
+    }
+
+    Ok(())
 }
 
-fn get_relative_path(state: &State<AppState>, full_path: &PathBuf) -> AnyResult<PathBuf> {
-    let storage = state.get_site()?.storage.clone();
-    let relative_path = full_path.strip_prefix(storage)?;
+// Moved files keep their owners, while copied files belong to the user who copied them.
+async fn update_file_owners(
+    pool: &Pool<Sqlite>,
+    storage: &str,
+    task: &CopyMoveTask,
+) -> AnyResult<()> {
+    let filename = task
+        .source
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid source: {:?}", task.source))?;
+    let source = task
+        .source
+        .strip_prefix(storage)?
+        .to_string_lossy()
+        .to_string();
+    let target = task.target.join(filename);
+    let target_str = target.strip_prefix(storage)?.to_string_lossy().to_string();
+
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+
+    if task.is_copy {
+        let storage = storage.to_owned();
+        let files =
+            rocket::tokio::task::spawn_blocking(move || copied_files(&target, &storage)).await??;
+        for (path, size) in files {
+            UploadedFile::new(&path, task.user_id, size)
+                .upsert_query(&mut tx)
+                .await?;
+        }
+    } else {
+        UploadedFile::update_all_sub_path_query(&mut tx, &source, &target_str).await?;
+    }
+
+    tx.commit().await?;
 
-    Ok(relative_path.to_owned())
+    Ok(())
 }
 
-async fn search_dir_all(
-    state: &State<AppState>,
-    keywords: &Vec<&str>,
-    user_permission: i8,
-) -> AnyResult<Vec<File>> {
+// The paths relative to the storage and the sizes of the files under the target.
+fn copied_files(target: &Path, storage: &str) -> AnyResult<Vec<(String, i64)>> {
+    let mut files = vec![];
+    for entry in WalkDir::new(target).follow_links(false).into_iter() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path().strip_prefix(storage)?.to_string_lossy();
+        files.push((path.to_string(), entry.metadata()?.len() as i64));
+    }
+
+    Ok(files)
+}
+
+fn get_target_path(access: &AccessControl, path: &str) -> AnyResult<PathBuf> {
+    let target_path = access.resolve(path)?;
+
+    if !target_path.exists() {
+        return Err(anyhow::anyhow!("Invalid path: {:?}", target_path));
+    }
+
+    Ok(target_path)
+}
+
+fn search_dir_all(keywords: &Vec<&str>, access: &AccessControl) -> AnyResult<Vec<File>> {
     let mut results = vec![];
-    let mut conn = state.get_pool_conn().await?;
-    let hiddens = Hidden::find_all(&mut conn).await?;
-    let storage = state.get_site()?.storage.clone();
 
-    for entry in WalkDir::new(&storage).follow_links(false).into_iter() {
+    for entry in WalkDir::new(access.root()).follow_links(false).into_iter() {
         let entry = entry?;
         let path = entry.path();
-        if contains_all_keywords(path, keywords)
-            && max_permission_parent(&path.to_owned(), &storage, &hiddens) <= user_permission
-        {
+        if contains_all_keywords(path, keywords) && access.can(path, Access::Read) {
             let path_buf = PathBuf::from(path);
-            let file = File::from_path(&path_buf, true, &storage, 0)?;
+            let file = File::from_path(&path_buf, true, access.root(), 0)?;
             results.push(file);
         }
     }
@@ -419,22 +676,6 @@ fn get_least_permission(file_path: &PathBuf, storage: &str, hiddens: &Vec<Hidden
     0
 }
 
-// Check the max permission value of all the parents of the input file path.
-fn max_permission_parent(file_path: &PathBuf, storage: &str, hiddens: &Vec<Hidden>) -> i8 {
-    let mut least_permission = 0;
-    let storage_path = PathBuf::from(storage);
-
-    for hidden in hiddens.iter() {
-        let hidden_full_path = storage_path.join(&hidden.path);
-
-        if file_path.starts_with(&hidden_full_path) && hidden.least_permission > least_permission {
-            least_permission = hidden.least_permission;
-        }
-    }
-
-    least_permission
-}
-
 // All keywords are lower case before sending to backend
 fn contains_all_keywords(path: &Path, keywords: &Vec<&str>) -> bool {
     let filename = path.file_name().unwrap().to_str().unwrap().to_lowercase();
@@ -466,38 +707,3 @@ fn contains_all_keywords(path: &Path, keywords: &Vec<&str>) -> bool {
 
     return true;
 }
-
-async fn zip_dir<W: AsyncWrite + Unpin>(
-    writer: &mut W,
-    path: &PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut writer = ZipFileWriter::new(writer);
-    let mut it = WalkDir::new(path).into_iter();
-    while let Some(Ok(entry)) = it.next() {
-        if !entry.file_type().is_file() {
-            continue;
-        }
-
-        if entry.path().symlink_metadata().is_err() {
-            continue;
-        }
-
-        let parent_dir = match path.parent() {
-            Some(p) => p,
-            None => path,
-        };
-        let filename = match entry.path().strip_prefix(&parent_dir) {
-            Ok(v) => v.to_str().unwrap(),
-            Err(_) => continue,
-        };
-        let entry_op =
-            ZipEntryBuilder::new(filename.to_owned(), Compression::Stored).unix_permissions(0o644);
-        let mut file = tokio::fs::File::open(entry.path()).await?;
-        let mut file_writer = writer.write_entry_stream(entry_op).await?;
-        io::copy(&mut file, &mut file_writer).await?;
-        file_writer.close().await?;
-    }
-
-    writer.close().await?;
-    Ok(())
-}
@@ -0,0 +1,180 @@
+use crate::entity::acl::{Acl, AclSubject};
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use crate::entity::group::{Group, GroupMember};
+use crate::entity::hidden::Hidden;
+use crate::entity::request::GroupRequest;
+use crate::entity::response::UserResponse;
+use crate::entity::user::User;
+use crate::service::app_state::AppState;
+use crate::service::audit::Auditor;
+use crate::service::auth::AuthAdmin;
+use rocket::serde::json::Json;
+use rocket::{Route, State};
+use sqlx::Connection;
+
+pub fn route() -> Vec<Route> {
+    routes![
+        list_groups,
+        create_group,
+        update_group,
+        delete_group,
+        list_group_members,
+        add_group_member,
+        remove_group_member
+    ]
+}
+
+#[get("/groups")]
+async fn list_groups(
+    state: &State<AppState>,
+    _admin: AuthAdmin,
+) -> Result<Json<Vec<Group>>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+
+    Ok(Json(Group::find_all(&mut conn).await?))
+}
+
+#[post("/groups", data = "<req_body>")]
+async fn create_group(
+    state: &State<AppState>,
+    req_body: Json<GroupRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<Json<Group>, Error> {
+    audit.log(admin.uid, AuditAction::CreateGroup, "", &req_body.name);
+
+    let name = req_body.name.trim();
+    if name.is_empty() {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    if Group::find_by_name(name, &mut conn).await?.is_some() {
+        return Err(Error::Conflict);
+    }
+
+    let mut group = Group::new(name);
+    let mut tx = conn.begin().await?;
+    group.group_id = group.insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(group))
+}
+
+#[put("/groups/<group_id>", data = "<req_body>")]
+async fn update_group(
+    state: &State<AppState>,
+    group_id: i64,
+    req_body: Json<GroupRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::UpdateGroup,
+        "",
+        &group_id.to_string(),
+    );
+
+    let name = req_body.name.trim();
+    if name.is_empty() {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    Group::find_by_id(group_id, &mut conn).await?.ok_or(404)?;
+    if let Some(group) = Group::find_by_name(name, &mut conn).await? {
+        if group.group_id != group_id {
+            return Err(Error::Conflict);
+        }
+    }
+
+    let mut tx = conn.begin().await?;
+    Group::update_name_query(group_id, name, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Remove the memberships and the access rules targeting the group as well.
+#[delete("/groups/<group_id>")]
+async fn delete_group(
+    state: &State<AppState>,
+    group_id: i64,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::DeleteGroup,
+        "",
+        &group_id.to_string(),
+    );
+
+    let mut conn = state.get_pool_conn().await?;
+    Group::find_by_id(group_id, &mut conn).await?.ok_or(404)?;
+
+    let mut tx = conn.begin().await?;
+    GroupMember::delete_group_members_query(group_id, &mut tx).await?;
+    Acl::delete_subject_query(AclSubject::Group, group_id, &mut tx).await?;
+    Hidden::clear_group_query(group_id, &mut tx).await?;
+    Group::delete_query(group_id, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[get("/groups/<group_id>/members")]
+async fn list_group_members(
+    state: &State<AppState>,
+    group_id: i64,
+    _admin: AuthAdmin,
+) -> Result<Json<Vec<UserResponse>>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    Group::find_by_id(group_id, &mut conn).await?.ok_or(404)?;
+    let members = GroupMember::find_group_members(group_id, &mut conn).await?;
+
+    Ok(Json(members.into_iter().map(UserResponse::from).collect()))
+}
+
+#[put("/groups/<group_id>/members/<uid>")]
+async fn add_group_member(
+    state: &State<AppState>,
+    group_id: i64,
+    uid: i64,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    let member = format!("{}:{}", group_id, uid);
+    audit.log(admin.uid, AuditAction::AddGroupMember, "", &member);
+
+    let mut conn = state.get_pool_conn().await?;
+    Group::find_by_id(group_id, &mut conn).await?.ok_or(404)?;
+    User::find_user_by_id(uid, &mut conn).await?.ok_or(404)?;
+
+    let mut tx = conn.begin().await?;
+    GroupMember::insert_query(group_id, uid, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[delete("/groups/<group_id>/members/<uid>")]
+async fn remove_group_member(
+    state: &State<AppState>,
+    group_id: i64,
+    uid: i64,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    let member = format!("{}:{}", group_id, uid);
+    audit.log(admin.uid, AuditAction::RemoveGroupMember, "", &member);
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    GroupMember::delete_query(group_id, uid, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -1,6 +1,13 @@
 use rocket::Route;
+mod acl;
+mod audit;
+mod fetch;
 mod files;
+mod group;
+mod quota;
+mod share;
 mod sys;
+mod tus;
 mod upload;
 mod user;
 
@@ -10,6 +17,13 @@ pub fn serve() -> Vec<Route> {
     apis.append(&mut user::route());
     apis.append(&mut files::route());
     apis.append(&mut upload::route());
+    apis.append(&mut acl::route());
+    apis.append(&mut group::route());
+    apis.append(&mut quota::route());
+    apis.append(&mut audit::route());
+    apis.append(&mut share::route());
+    apis.append(&mut tus::route());
+    apis.append(&mut fetch::route());
 
     apis
 }
@@ -0,0 +1,162 @@
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use crate::entity::quota::{DirQuota, UploadedFile};
+use crate::entity::request::{DirQuotaRequest, UpdateQuotaRequest};
+use crate::entity::response::{DirUsageResponse, QuotaResponse, UsageResponse, UserUsageResponse};
+use crate::entity::user::User;
+use crate::service::access;
+use crate::service::app_state::AppState;
+use crate::service::audit::Auditor;
+use crate::service::auth::{AuthAdmin, AuthUser};
+use crate::service::quota;
+use crate::util;
+use rocket::serde::json::Json;
+use rocket::{Route, State};
+use sqlx::Connection;
+use std::path::Path;
+
+pub fn route() -> Vec<Route> {
+    routes![
+        user_usage,
+        list_quotas,
+        update_user_quota,
+        update_dir_quota,
+        delete_dir_quota
+    ]
+}
+
+#[get("/user/usage")]
+async fn user_usage(state: &State<AppState>, user: AuthUser) -> Result<Json<UsageResponse>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let quota_bytes = match User::find_user_by_id(user.uid, &mut conn).await? {
+        Some(u) => u.quota_bytes,
+        None => 0,
+    };
+    let used_bytes = UploadedFile::find_user_usage(user.uid, &mut conn).await?;
+
+    Ok(Json(UsageResponse {
+        used_bytes,
+        quota_bytes,
+    }))
+}
+
+#[get("/quotas")]
+async fn list_quotas(
+    state: &State<AppState>,
+    _admin: AuthAdmin,
+) -> Result<Json<QuotaResponse>, Error> {
+    let storage = state.get_site()?.storage.clone();
+    let mut conn = state.get_pool_conn().await?;
+
+    let mut users = vec![];
+    for user in User::find_all(&mut conn).await? {
+        let used_bytes = UploadedFile::find_user_usage(user.user_id, &mut conn).await?;
+        users.push(UserUsageResponse {
+            user_id: user.user_id,
+            username: user.username,
+            used_bytes,
+            quota_bytes: user.quota_bytes,
+        });
+    }
+
+    let mut dirs = vec![];
+    for dir_quota in DirQuota::find_all(&mut conn).await? {
+        dirs.push(DirUsageResponse {
+            used_bytes: quota::dir_size(&Path::new(&storage).join(&dir_quota.path)).await?,
+            path: dir_quota.path,
+            max_bytes: dir_quota.max_bytes,
+        });
+    }
+
+    Ok(Json(QuotaResponse { users, dirs }))
+}
+
+// A quota of 0 removes the limit.
+#[put("/users/<uid>/quota", data = "<req_body>")]
+async fn update_user_quota(
+    state: &State<AppState>,
+    uid: i64,
+    req_body: Json<UpdateQuotaRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::UpdateUserQuota,
+        "",
+        &uid.to_string(),
+    );
+
+    if req_body.max_bytes < 0 {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    User::find_user_by_id(uid, &mut conn).await?.ok_or(404)?;
+
+    let mut tx = conn.begin().await?;
+    User::update_quota_query(uid, req_body.max_bytes, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[put("/quotas/dir", data = "<req_body>")]
+async fn update_dir_quota(
+    state: &State<AppState>,
+    req_body: Json<DirQuotaRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::UpdateDirQuota, &req_body.path, "");
+
+    if req_body.max_bytes <= 0 {
+        return Err(Error::BadRequest);
+    }
+
+    let path = get_quota_path(state, &req_body.path)?;
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    DirQuota::new(&path, req_body.max_bytes)
+        .upsert_query(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[delete("/quotas/dir?<path>")]
+async fn delete_dir_quota(
+    state: &State<AppState>,
+    path: &str,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::DeleteDirQuota, path, "");
+
+    let decoded = util::parse_encoded_url(path)?;
+    let relative = access::resolve_under(Path::new(""), &decoded).map_err(|_| 400)?;
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    DirQuota::delete_query(&relative.to_string_lossy(), &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Normalize the path relative to the storage, which must be an existing dir.
+fn get_quota_path(state: &State<AppState>, path: &str) -> Result<String, Error> {
+    let decoded = util::parse_encoded_url(path)?;
+    let relative = access::resolve_under(Path::new(""), &decoded).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+
+    let storage = state.get_site()?.storage.clone();
+    if !Path::new(&storage).join(&relative).is_dir() {
+        return Err(Error::BadRequest);
+    }
+
+    Ok(relative.to_string_lossy().to_string())
+}
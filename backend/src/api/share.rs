@@ -0,0 +1,462 @@
+use crate::entity::acl::Access;
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use crate::entity::file::File;
+use crate::entity::login_attempt::LoginAttempt;
+use crate::entity::request::{GenerateLinkRequest, SharePasswordForm};
+use crate::entity::response::{FileResponse, ShareResponse, ShareStatsResponse};
+use crate::entity::share::{Share, ShareType};
+use crate::entity::share_access::{self, ShareAccess};
+use crate::entity::user::User;
+use crate::service::access::{self, AccessControl};
+use crate::service::app_state::AppState;
+use crate::service::audit::Auditor;
+use crate::service::auth::AuthUser;
+use crate::service::client::ClientInfo;
+use crate::service::range::{Range, RangedFile};
+use crate::service::share_password::SharePassword;
+use crate::util::constants::ZIP_BUFFER_SIZE;
+use crate::util::{self, file_system};
+use rocket::form::Form;
+use rocket::fs::NamedFile;
+use rocket::futures::stream::Stream;
+use rocket::response::stream::{stream, ByteStream};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::tokio::fs;
+use rocket::tokio::io::AsyncReadExt;
+use rocket::{Request, Route, State};
+use sqlx::Connection;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+pub fn route() -> Vec<Route> {
+    routes![
+        create_share,
+        get_share,
+        post_share,
+        share_dir_content,
+        share_file_content,
+        list_shares,
+        share_stats,
+        revoke_share
+    ]
+}
+
+#[post("/file/share", data = "<req_body>")]
+async fn create_share(
+    state: &State<AppState>,
+    req_body: Json<GenerateLinkRequest>,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<Json<ShareResponse>, Error> {
+    audit.log(user.uid, AuditAction::Share, &req_body.path, "");
+
+    let access = AccessControl::load(state, &user).await?;
+    let target_path = access.resolve(&req_body.path).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+
+    // Upload shares need a dir the creator can write into.
+    let is_upload = req_body.share_type == Some(ShareType::Upload);
+    let (exists, required) = if is_upload {
+        (target_path.is_dir(), Access::Write)
+    } else {
+        (target_path.exists(), Access::Share)
+    };
+
+    if !exists {
+        return Err(Error::BadRequest);
+    }
+
+    if !access.can(&target_path, required) {
+        return Err(Error::Forbidden);
+    }
+
+    if req_body.expire > 0 && req_body.expire <= util::get_utc_seconds() {
+        return Err(Error::BadRequest);
+    }
+
+    // Shares are resolved from the storage, whatever the home directory of the user is.
+    let share = Share::new(&access.storage_relative(&target_path)?, user.uid, &req_body)?;
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    let share_id = share.insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    let share = Share::find_by_id(share_id, &mut conn)
+        .await?
+        .ok_or(Error::InternalServerError)?;
+
+    Ok(Json(share.into()))
+}
+
+#[get("/share/<slug>")]
+async fn get_share(
+    state: &State<AppState>,
+    slug: &str,
+    password: SharePassword,
+    range_header: Range,
+    client: ClientInfo,
+) -> Result<ShareContent, Error> {
+    share_content(state, slug, &password, range_header, &client).await
+}
+
+// Posted by the share page, where visitors enter the password of a share.
+#[post("/share/<slug>", data = "<form>")]
+async fn post_share(
+    state: &State<AppState>,
+    slug: &str,
+    form: Form<SharePasswordForm>,
+    range_header: Range,
+    client: ClientInfo,
+) -> Result<ShareContent, Error> {
+    let password = SharePassword::from(form.into_inner());
+
+    share_content(state, slug, &password, range_header, &client).await
+}
+
+#[get("/share/<slug>/dir?<path>")]
+async fn share_dir_content(
+    state: &State<AppState>,
+    slug: &str,
+    path: Option<&str>,
+    password: SharePassword,
+    client: ClientInfo,
+) -> Result<Json<Vec<File>>, Error> {
+    let (share, share_path) = open_share(state, slug, &password, &client).await?;
+    let target_path = resolve_in_share(&share_path, path)?;
+    if !target_path.is_dir() {
+        return Err(Error::BadRequest);
+    }
+
+    let access = owner_access(state, &share).await?;
+    let mut dir_iterator = fs::read_dir(target_path).await?;
+    let mut content: Vec<File> = Vec::new();
+    while let Some(entry) = dir_iterator.next_entry().await? {
+        let path = entry.path();
+        if access.can(&path, Access::Read) {
+            content.push(File::from_path(&path, false, &share_path, 0)?);
+        }
+    }
+
+    Ok(Json(content))
+}
+
+#[get("/share/<slug>/file/<path>")]
+async fn share_file_content(
+    state: &State<AppState>,
+    slug: &str,
+    path: &str,
+    password: SharePassword,
+    range_header: Range,
+    client: ClientInfo,
+) -> Result<FileResponse, Error> {
+    let (share, share_path) = open_share(state, slug, &password, &client).await?;
+    let target_path = resolve_in_share(&share_path, Some(path))?;
+    if !target_path.is_file() {
+        return Err(Error::NotFound);
+    }
+
+    let access = owner_access(state, &share).await?;
+    if !access.can(&target_path, Access::Read) {
+        return Err(Error::NotFound);
+    }
+
+    let relative_path = target_path
+        .strip_prefix(&share_path)
+        .unwrap_or(&target_path);
+    record_download(
+        state,
+        &share,
+        &client,
+        &relative_path.to_string_lossy(),
+        Some(&target_path),
+        range_header.range,
+    )
+    .await?;
+    send_file(target_path, range_header).await
+}
+
+// Admins see the active shares of all users, others only see their own.
+#[get("/shares")]
+async fn list_shares(
+    state: &State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<ShareResponse>>, Error> {
+    let user_id = if user.permission >= 9 {
+        None
+    } else {
+        Some(user.uid)
+    };
+
+    let mut conn = state.get_pool_conn().await?;
+    let shares = Share::find_active(user_id, &mut conn).await?;
+
+    Ok(Json(shares.into_iter().map(|s| s.into()).collect()))
+}
+
+#[get("/shares/<share_id>/stats")]
+async fn share_stats(
+    state: &State<AppState>,
+    share_id: i64,
+    user: AuthUser,
+) -> Result<Json<ShareStatsResponse>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let share = Share::find_by_id(share_id, &mut conn).await?.ok_or(404)?;
+    if share.user_id != user.uid && user.permission < 9 {
+        return Err(Error::Forbidden);
+    }
+
+    let accesses = ShareAccess::find_by_share(share_id, &mut conn).await?;
+
+    Ok(Json(ShareStatsResponse::from_accesses(share, accesses)))
+}
+
+#[delete("/shares/<share_id>")]
+async fn revoke_share(
+    state: &State<AppState>,
+    share_id: i64,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        user.uid,
+        AuditAction::RevokeShare,
+        "",
+        &share_id.to_string(),
+    );
+
+    let mut conn = state.get_pool_conn().await?;
+    let share = Share::find_by_id(share_id, &mut conn).await?.ok_or(404)?;
+    if share.user_id != user.uid && user.permission < 9 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut tx = conn.begin().await?;
+    Share::revoke_query(share_id, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+enum ShareContent {
+    File(FileResponse),
+    Zip(ByteStream<ZipChunks>),
+}
+
+// Not derived, as the byte stream only responds with the lifetime of the request.
+impl<'r> Responder<'r, 'r> for ShareContent {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            ShareContent::File(file) => file.respond_to(req),
+            ShareContent::Zip(zip) => zip.respond_to(req),
+        }
+    }
+}
+
+type ZipChunks = Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>;
+
+// A shared file is sent as it is, and a shared dir is sent as a zip.
+async fn share_content(
+    state: &AppState,
+    slug: &str,
+    password: &SharePassword,
+    range_header: Range,
+    client: &ClientInfo,
+) -> Result<ShareContent, Error> {
+    let (share, share_path) = open_share(state, slug, password, client).await?;
+
+    if share_path.is_dir() {
+        let access = owner_access(state, &share).await?;
+        record_download(state, &share, client, "", None, None).await?;
+
+        return Ok(ShareContent::Zip(zip_stream(access, share_path)));
+    }
+
+    let range = range_header.range;
+    record_download(state, &share, client, "", Some(&share_path), range).await?;
+    Ok(ShareContent::File(
+        send_file(share_path, range_header).await?,
+    ))
+}
+
+// Return the share with the full path of its target, if it can still be visited.
+async fn open_share(
+    state: &AppState,
+    slug: &str,
+    password: &SharePassword,
+    client: &ClientInfo,
+) -> Result<(Share, PathBuf), Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let share = Share::find_by_slug(slug, &mut conn).await?.ok_or(404)?;
+    // The content of upload shares is never shown to visitors.
+    if share.is_upload() || !share.is_open(util::get_utc_seconds()) {
+        return Err(Error::NotFound);
+    }
+
+    check_share_password(state, &share, password, client).await?;
+
+    let storage = state.get_site()?.storage.clone();
+    let share_path =
+        access::resolve_under(Path::new(&storage), Path::new(&share.path)).map_err(|e| {
+            eprintln!("{}", e);
+            400
+        })?;
+
+    if !share_path.exists() {
+        return Err(Error::NotFound);
+    }
+
+    // A visitor already counted for a shared dir keeps getting the rest of its files.
+    if !share.within_limit() {
+        let visited = share_path.is_dir()
+            && ShareAccess::has_visited(share.share_id, &client.ip, &client.user_agent, &mut conn)
+                .await?;
+        if !visited {
+            return Err(Error::NotFound);
+        }
+    }
+
+    Ok((share, share_path))
+}
+
+// Wrong passwords count towards the lockout of the client and of the share, the same as
+// failed logins.
+pub async fn check_share_password(
+    state: &AppState,
+    share: &Share,
+    password: &SharePassword,
+    client: &ClientInfo,
+) -> Result<(), Error> {
+    if !share.has_password() {
+        return Ok(());
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::share_key(&share.slug),
+    ];
+    if !LoginAttempt::start(&attempt_keys, &mut conn).await? {
+        return Err(Error::TooManyRequests);
+    }
+
+    if !share.check_password(password.password.as_deref()) {
+        return Err(Error::Unauthorized);
+    }
+    LoginAttempt::take_back(&attempt_keys, &mut conn).await?;
+
+    Ok(())
+}
+
+// The path is URL encoded and relative to the shared dir, which it never escapes.
+fn resolve_in_share(share_path: &Path, path: Option<&str>) -> Result<PathBuf, Error> {
+    let path = match path {
+        Some(p) => util::parse_encoded_url(p)?,
+        None => return Ok(share_path.to_path_buf()),
+    };
+
+    access::resolve_under(share_path, &path).map_err(|e| {
+        eprintln!("{}", e);
+        Error::BadRequest
+    })
+}
+
+// Visitors of a shared dir see no more than its creator does.
+async fn owner_access(state: &AppState, share: &Share) -> Result<AccessControl, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let owner = User::find_user_by_id(share.user_id, &mut conn)
+        .await?
+        .ok_or(404)?;
+    let user = AuthUser {
+        uid: owner.user_id,
+        permission: owner.permission,
+        read_only: true,
+    };
+
+    Ok(AccessControl::load(state, &user).await?)
+}
+
+// Every request is logged, while a download is counted once, not for every later
+// range of the same file. A shared dir counts one download for each visitor, however
+// many of its files they get. The file is `None` for the zip of a shared dir.
+async fn record_download(
+    state: &AppState,
+    share: &Share,
+    client: &ClientInfo,
+    path: &str,
+    file: Option<&Path>,
+    range: Option<(u64, u64)>,
+) -> Result<(), Error> {
+    let (served, completed) = match file {
+        Some(f) => {
+            let size = fs::metadata(f).await?.len();
+            match share_access::served_range(range, size) {
+                Some((start, end, completed)) => (Some((start, end)), completed),
+                None => (None, false),
+            }
+        }
+        None => (None, true),
+    };
+    let access = ShareAccess::new(
+        share.share_id,
+        &client.ip,
+        &client.user_agent,
+        path,
+        served,
+        completed,
+    );
+
+    let mut conn = state.get_pool_conn().await?;
+    let counted = if file.is_none() || !path.is_empty() {
+        !ShareAccess::has_visited(share.share_id, &client.ip, &client.user_agent, &mut conn).await?
+    } else {
+        !matches!(range, Some((start, _)) if start > 0)
+    };
+
+    let mut tx = conn.begin().await?;
+    access.insert_query(&mut tx).await?;
+    if counted {
+        Share::increase_download_query(share.share_id, &mut tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn send_file(path: PathBuf, range_header: Range) -> Result<FileResponse, Error> {
+    match range_header.range {
+        Some(range) => {
+            let ranged_file = RangedFile::new(range, path).await?;
+            Ok(FileResponse::Range(ranged_file))
+        }
+        None => Ok(FileResponse::Binary(NamedFile::open(path).await?)),
+    }
+}
+
+fn zip_stream(access: AccessControl, dir: PathBuf) -> ByteStream<ZipChunks> {
+    let (mut writer, mut reader) = tokio::io::duplex(ZIP_BUFFER_SIZE);
+    rocket::tokio::spawn(async move {
+        let readable = |p: &Path| access.can(p, Access::Read);
+        if let Err(e) = file_system::zip_dir(&mut writer, &dir, readable).await {
+            println!("Error ziping dir: {}", e);
+        }
+    });
+
+    let chunks = stream! {
+        loop {
+            let mut buf = vec![0; ZIP_BUFFER_SIZE];
+            let r = reader.read(&mut buf).await.unwrap();
+            if r == 0 {
+                break;
+            }
+            buf.truncate(r);
+            yield buf;
+        }
+    };
+
+    ByteStream(Box::pin(chunks))
+}
@@ -1,10 +1,18 @@
+use crate::entity::acl::Acl;
+use crate::entity::audit::AuditAction;
 use crate::entity::error::Error;
 use crate::entity::hidden::Hidden;
+use crate::entity::login_attempt::LoginAttempt;
+use crate::entity::quota::{DirQuota, UploadedFile};
 use crate::entity::request::{SetupRequest, UpdateSiteRequest};
 use crate::entity::response::{AppNeedUpdateResponse, SiteBriefResponse, SiteFullResponse};
+use crate::entity::share::Share;
+use crate::entity::share_access::ShareAccess;
 use crate::entity::site::Site;
+use crate::entity::upload_task::UploadTask;
 use crate::entity::user::User;
 use crate::service::app_state::AppState;
+use crate::service::audit::Auditor;
 use crate::service::auth::AuthAdmin;
 use crate::service::token::AccessToken;
 use crate::util::{self, file_system};
@@ -20,12 +28,21 @@ pub fn route() -> Vec<Route> {
         setup,
         config,
         update_site,
-        check_need_update
+        check_need_update,
+        list_lockouts,
+        clear_lockouts,
+        clear_lockout
     ]
 }
 
 #[post("/sys/setup", data = "<req_body>")]
-async fn setup(state: &State<AppState>, req_body: Json<SetupRequest>) -> Result<(), Error> {
+async fn setup(
+    state: &State<AppState>,
+    req_body: Json<SetupRequest>,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(0, AuditAction::Setup, &req_body.storage, &req_body.username);
+
     if !state.get_first_run() {
         return Err(Error::Forbidden);
     }
@@ -125,7 +142,10 @@ async fn update_site(
     state: &State<AppState>,
     token: AccessToken,
     req_body: Json<UpdateSiteRequest>,
+    audit: Auditor<'_>,
 ) -> Result<(), Error> {
+    audit.log(token.uid, AuditAction::UpdateSite, "", "");
+
     if token.permission != 9 {
         return Err(Error::Unauthorized);
     }
@@ -138,10 +158,16 @@ async fn update_site(
     let storage_str = storage.to_str().unwrap().to_owned();
     let mut conn = state.get_pool_conn().await?;
     let mut site = Site::read(&mut conn).await?.ok_or(500)?;
-    // If storage location changed, delete all records in HIDDEN table.
+    // If storage location changed, delete all records with paths in the old storage.
     if site.storage != storage_str {
         let mut tx = conn.begin().await?;
         Hidden::delete_all_query(&mut tx).await?;
+        Acl::delete_all_query(&mut tx).await?;
+        DirQuota::delete_all_query(&mut tx).await?;
+        UploadedFile::delete_all_query(&mut tx).await?;
+        Share::delete_all_query(&mut tx).await?;
+        ShareAccess::delete_orphans_query(&mut tx).await?;
+        UploadTask::delete_all_query(&mut tx).await?;
         tx.commit().await?;
     }
 
@@ -150,6 +176,9 @@ async fn update_site(
     site.update_freq = req_body.update_freq.to_owned();
     site.storage = storage_str;
     site.allow_guest = if req_body.allow_guest { 1 } else { 0 };
+    if let Some(require_admin_2fa) = req_body.require_admin_2fa {
+        site.require_admin_2fa = if require_admin_2fa { 1 } else { 0 };
+    }
 
     let mut tx = conn.begin().await?;
     site.update(&mut tx).await?;
@@ -176,3 +205,47 @@ async fn check_need_update(
     let url = util::get_verion_url();
     Ok(Json(AppNeedUpdateResponse { need, url }))
 }
+
+#[get("/sys/lockouts")]
+async fn list_lockouts(
+    state: &State<AppState>,
+    _admin: AuthAdmin,
+) -> Result<Json<Vec<LoginAttempt>>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let lockouts = LoginAttempt::find_all_locked(&mut conn).await?;
+
+    Ok(Json(lockouts))
+}
+
+#[delete("/sys/lockouts")]
+async fn clear_lockouts(
+    state: &State<AppState>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::ClearLockout, "", "");
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    LoginAttempt::delete_all_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[delete("/sys/lockouts/<key>")]
+async fn clear_lockout(
+    state: &State<AppState>,
+    key: &str,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::ClearLockout, "", key);
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    LoginAttempt::delete_query(key, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
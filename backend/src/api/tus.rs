@@ -0,0 +1,213 @@
+use super::upload;
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use crate::entity::request::UploadRequest;
+use crate::entity::upload_task::{ConflictPolicy, UploadTask};
+use crate::service::app_state::AppState;
+use crate::service::audit::Auditor;
+use crate::service::auth::AuthUser;
+use crate::service::tus::{TusHeaders, TusResponse};
+use crate::util::constants::{TUS_CHECKSUM_ALGORITHMS, TUS_EXTENSIONS, TUS_VERSION};
+use crate::util::hash::HashAlgorithm;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::tokio::fs::{self, File, OpenOptions};
+use rocket::tokio::io::{self, AsyncWriteExt};
+use rocket::{Route, State};
+use sqlx::Connection;
+use std::path::PathBuf;
+
+// A tus upload is an upload task, with all the bytes received saved as its first slice,
+// so it is finished the same way as the slice protocol.
+pub fn route() -> Vec<Route> {
+    routes![
+        tus_options,
+        create_tus_upload,
+        tus_upload_offset,
+        patch_tus_upload,
+        delete_tus_upload
+    ]
+}
+
+#[options("/tus")]
+async fn tus_options() -> TusResponse {
+    TusResponse::new(Status::NoContent)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS)
+        .header("Tus-Checksum-Algorithm", TUS_CHECKSUM_ALGORITHMS)
+}
+
+// The metadata must have the filename, and the target dir encoded the same way as
+// `pre_upload`, which is the root of the user if absent. An optional hash of the whole
+// file is verified when the upload is finished, and the optional conflict policy and
+// relative dir are the same as for `pre_upload`.
+#[post("/tus")]
+async fn create_tus_upload(
+    state: &State<AppState>,
+    headers: TusHeaders,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<TusResponse, Error> {
+    let filename = headers.upload_metadata.get("filename").cloned();
+    let target = headers.upload_metadata.get("target").cloned();
+    audit.log(
+        user.uid,
+        AuditAction::StartUpload,
+        target.as_deref().unwrap_or(""),
+        filename.as_deref().unwrap_or(""),
+    );
+
+    let conflict = match headers.upload_metadata.get("conflict") {
+        Some(name) => Some(ConflictPolicy::from_name(name).ok_or(Error::BadRequest)?),
+        None => None,
+    };
+
+    let req = UploadRequest {
+        filename: filename.ok_or(Error::BadRequest)?,
+        size: headers.upload_length.ok_or(Error::BadRequest)?,
+        target: target.unwrap_or_default(),
+        hash: headers
+            .upload_metadata
+            .get("hash")
+            .cloned()
+            .unwrap_or_default(),
+        conflict,
+        relative_dir: headers.upload_metadata.get("relative_dir").cloned(),
+        batch_id: None,
+    };
+    let uuid = upload::start_user_upload(state, &req, &user).await?;
+
+    // Create the data file, so an empty upload is finished at once.
+    let mut conn = state.get_pool_conn().await?;
+    let task = UploadTask::find_by_uuid(&uuid, &mut conn)
+        .await?
+        .ok_or(Error::InternalServerError)?;
+    fs::File::create(data_file(&task)).await?;
+    if req.size == 0 {
+        finish(state, &task).await?;
+    }
+
+    Ok(TusResponse::new(Status::Created).header("Location", format!("/api/tus/{}", uuid)))
+}
+
+#[head("/tus/<uuid>")]
+async fn tus_upload_offset(
+    state: &State<AppState>,
+    uuid: &str,
+    user: AuthUser,
+) -> Result<TusResponse, Error> {
+    let task = find_tus_task(state, uuid, &user).await?;
+    let offset = current_offset(&task).await?;
+
+    Ok(TusResponse::new(Status::Ok)
+        .header("Upload-Offset", offset)
+        .header("Upload-Length", task.size)
+        .header("Cache-Control", "no-store"))
+}
+
+// The body is appended only if its checksum matches, when the client sends one. The
+// requests of an upload are handled one at a time from the offset check to the finish.
+#[patch("/tus/<uuid>", data = "<data>")]
+async fn patch_tus_upload(
+    state: &State<AppState>,
+    uuid: &str,
+    headers: TusHeaders,
+    data: Data<'_>,
+    user: AuthUser,
+) -> Result<TusResponse, Error> {
+    if headers.content_type.as_deref() != Some("application/offset+octet-stream") {
+        return Ok(TusResponse::new(Status::UnsupportedMediaType));
+    }
+
+    let upload_offset = headers.upload_offset.ok_or(Error::BadRequest)?;
+    let _lock = state.upload_locks.lock(uuid).await;
+    let task = find_tus_task(state, uuid, &user).await?;
+    let offset = current_offset(&task).await?;
+    if upload_offset != offset {
+        return Err(Error::Conflict);
+    }
+
+    let remaining = (task.size as u64).saturating_sub(offset);
+    let data_file = data_file(&task);
+    match &headers.upload_checksum {
+        Some((algorithm, digest)) => {
+            let algorithm = HashAlgorithm::from_name(algorithm).ok_or(Error::BadRequest)?;
+            let part_file = task.temp_dir().join("1.part");
+            let written = upload::write_body(data, remaining, &part_file, algorithm).await;
+            match written {
+                Ok((_, actual)) if &actual == digest => {}
+                result => {
+                    if let Err(e) = result {
+                        eprintln!("Error when writing request body: {}", e);
+                    }
+                    if part_file.exists() {
+                        fs::remove_file(&part_file).await?;
+                    }
+                    return Ok(TusResponse::new(Status::new(460)));
+                }
+            }
+
+            let mut part = File::open(&part_file).await?;
+            let mut file = OpenOptions::new().append(true).open(&data_file).await?;
+            io::copy(&mut part, &mut file).await?;
+            file.flush().await?;
+            fs::remove_file(&part_file).await?;
+        }
+        None => {
+            let mut file = OpenOptions::new().append(true).open(&data_file).await?;
+            io::copy(&mut data.open(remaining.bytes()), &mut file).await?;
+            file.flush().await?;
+        }
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    UploadTask::touch_query(&task.uuid, &mut tx).await?;
+    tx.commit().await?;
+
+    let offset = current_offset(&task).await?;
+    if offset == task.size as u64 {
+        finish(state, &task).await?;
+    }
+
+    Ok(TusResponse::new(Status::NoContent).header("Upload-Offset", offset))
+}
+
+#[delete("/tus/<uuid>")]
+async fn delete_tus_upload(
+    state: &State<AppState>,
+    uuid: &str,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<TusResponse, Error> {
+    audit.log(user.uid, AuditAction::CancelUpload, "", uuid);
+
+    find_tus_task(state, uuid, &user).await?;
+    upload::remove_upload_task(state, uuid, &user).await?;
+
+    Ok(TusResponse::new(Status::NoContent))
+}
+
+// tus clients expect 404 for an upload which does not exist.
+async fn find_tus_task(state: &AppState, uuid: &str, user: &AuthUser) -> Result<UploadTask, Error> {
+    match upload::find_task(state, uuid, user.uid, 0).await {
+        Err(Error::BadRequest) => Err(Error::NotFound),
+        result => result,
+    }
+}
+
+fn data_file(task: &UploadTask) -> PathBuf {
+    task.temp_dir().join("1")
+}
+
+async fn current_offset(task: &UploadTask) -> Result<u64, Error> {
+    match fs::metadata(data_file(task)).await {
+        Ok(meta) => Ok(meta.len()),
+        Err(_) => Err(Error::NotFound),
+    }
+}
+
+async fn finish(state: &AppState, task: &UploadTask) -> Result<(), Error> {
+    upload::complete_upload(state, task).await?;
+    Ok(())
+}
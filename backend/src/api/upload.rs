@@ -1,173 +1,909 @@
+use super::share;
+use crate::entity::acl::Access;
+use crate::entity::audit::AuditAction;
 use crate::entity::error::Error;
-use crate::entity::request::{CancelUploadRequest, UploadRequest};
-use crate::entity::upload_task::UploadTask;
+use crate::entity::quota::UploadedFile;
+use crate::entity::request::{CancelUploadRequest, PutFileOptions, UploadRequest};
+use crate::entity::response::{PutFileResponse, UploadResultResponse, UploadTaskResponse};
+use crate::entity::share::Share;
+use crate::entity::upload_task::{ConflictPolicy, UploadTask};
+use crate::service::access::{self, AccessControl};
 use crate::service::app_state::AppState;
-use crate::service::auth::AuthAdmin;
+use crate::service::audit::Auditor;
+use crate::service::auth::AuthUser;
+use crate::service::checksum::UploadChecksum;
+use crate::service::client::ClientInfo;
+use crate::service::content_length::ContentLength;
+use crate::service::quota;
+use crate::service::share_password::SharePassword;
 use crate::util;
+use crate::util::constants::COMBINE_BUFFER_SIZE;
+use crate::util::hash::{FileHash, HashAlgorithm, Hasher};
 use anyhow::Result as AnyResult;
+use rocket::data::{Data, ToByteUnit};
 use rocket::fs::TempFile;
 use rocket::serde::json::Json;
 use rocket::tokio::fs;
 use rocket::{Route, State};
-use std::path::PathBuf;
+use sqlx::{pool::PoolConnection, Connection, Sqlite};
+use std::path::{Path, PathBuf};
 use tokio::fs::File;
 use tokio::fs::OpenOptions;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 pub fn route() -> Vec<Route> {
-    routes![pre_upload, upload_file_slices, finish_upload, cancel_upload]
+    routes![
+        pre_upload,
+        upload_file_slices,
+        list_uploads,
+        upload_slices,
+        finish_upload,
+        cancel_upload,
+        share_pre_upload,
+        share_upload_file_slices,
+        share_upload_slices,
+        share_finish_upload,
+        put_file
+    ]
 }
 
-// TODO: breakpoint continue
 #[post("/pre-upload", data = "<req_body>")]
 async fn pre_upload(
     state: &State<AppState>,
     req_body: Json<UploadRequest>,
-    user: AuthAdmin,
+    user: AuthUser,
+    audit: Auditor<'_>,
 ) -> Result<String, Error> {
+    audit.log(
+        user.uid,
+        AuditAction::StartUpload,
+        &req_body.target,
+        &req_body.filename,
+    );
+
+    start_user_upload(state, &req_body, &user).await
+}
+
+#[post("/upload/<uuid>/<index>", data = "<file>")]
+async fn upload_file_slices(
+    state: &State<AppState>,
+    uuid: &str,
+    index: u64,
+    file: TempFile<'_>,
+    checksum: UploadChecksum,
+    user: AuthUser,
+) -> Result<(), Error> {
+    let task = find_task(state, uuid, user.uid, 0).await?;
+
+    save_file_slice(state, &task, index, file, &checksum).await
+}
+
+#[get("/uploads")]
+async fn list_uploads(
+    state: &State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<UploadTaskResponse>>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let tasks = UploadTask::find_user_tasks(user.uid, &mut conn).await?;
+
+    Ok(Json(
+        tasks
+            .into_iter()
+            .filter(|t| t.share_id == 0)
+            .map(UploadTaskResponse::from)
+            .collect(),
+    ))
+}
+
+// Clients resume an upload by sending the slices missing from this list.
+#[get("/upload/<uuid>/slices")]
+async fn upload_slices(
+    state: &State<AppState>,
+    uuid: &str,
+    user: AuthUser,
+) -> Result<Json<Vec<u64>>, Error> {
+    let task = find_task(state, uuid, user.uid, 0).await?;
+
+    Ok(Json(saved_slices(&task).await?))
+}
+
+#[post("/finish-upload/<uuid>")]
+async fn finish_upload(
+    state: &State<AppState>,
+    uuid: &str,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<Json<UploadResultResponse>, Error> {
+    audit.log(user.uid, AuditAction::FinishUpload, "", uuid);
+
+    let task = find_task(state, uuid, user.uid, 0).await?;
+    audit.log(user.uid, AuditAction::FinishUpload, &task.filename, uuid);
+
+    let saved_file = complete_upload(state, &task).await?;
+
+    Ok(Json(upload_result(&task, saved_file)))
+}
+
+#[post("/cancel-upload", data = "<req_body>")]
+async fn cancel_upload(
+    state: &State<AppState>,
+    req_body: Json<CancelUploadRequest>,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    let mut uuids = req_body.uuids.clone();
+    let mut conn = state.get_pool_conn().await?;
+    for batch_id in req_body.batch_ids.iter().filter(|b| !b.is_empty()) {
+        let tasks = UploadTask::find_batch_tasks(user.uid, batch_id, &mut conn).await?;
+        uuids.extend(tasks.into_iter().map(|t| t.uuid));
+    }
+    audit.log(user.uid, AuditAction::CancelUpload, "", &uuids.join(","));
+
+    for uuid in uuids.iter() {
+        remove_upload_task(state, uuid, &user).await?;
+    }
+
+    Ok(())
+}
+
+// Anonymous uploads through an upload share. The target in the request is ignored,
+// as the files always go into the shared dir, and are owned by the share creator.
+#[post("/share/<slug>/pre-upload", data = "<req_body>")]
+async fn share_pre_upload(
+    state: &State<AppState>,
+    slug: &str,
+    password: SharePassword,
+    client: ClientInfo,
+    req_body: Json<UploadRequest>,
+    audit: Auditor<'_>,
+) -> Result<String, Error> {
+    audit.log(0, AuditAction::StartUpload, "", &req_body.filename);
+
+    let (share, target_dir) = find_upload_share(state, slug).await?;
+    audit.log(0, AuditAction::StartUpload, &share.path, &req_body.filename);
+    share::check_share_password(state, &share, &password, &client).await?;
+
+    // Visitors only drop files into the shared dir, never create dirs in it.
+    let has_relative_dir = !req_body.relative_dir.as_deref().unwrap_or("").is_empty();
+    if !share.accepts_file_size(req_body.size) || has_relative_dir {
+        return Err(Error::BadRequest);
+    }
+
+    // Visitors never replace the files dropped before.
+    start_upload(
+        state,
+        &req_body,
+        share.user_id,
+        share.share_id,
+        target_dir,
+        ConflictPolicy::Rename,
+    )
+    .await
+}
+
+// The task uuid is only known after passing the password check.
+#[post("/share/<slug>/upload/<uuid>/<index>", data = "<file>")]
+async fn share_upload_file_slices(
+    state: &State<AppState>,
+    slug: &str,
+    uuid: &str,
+    index: u64,
+    file: TempFile<'_>,
+    checksum: UploadChecksum,
+) -> Result<(), Error> {
+    let (share, _) = find_upload_share(state, slug).await?;
+    let task = find_task(state, uuid, share.user_id, share.share_id).await?;
+
+    save_file_slice(state, &task, index, file, &checksum).await
+}
+
+#[get("/share/<slug>/upload/<uuid>/slices")]
+async fn share_upload_slices(
+    state: &State<AppState>,
+    slug: &str,
+    uuid: &str,
+) -> Result<Json<Vec<u64>>, Error> {
+    let (share, _) = find_upload_share(state, slug).await?;
+    let task = find_task(state, uuid, share.user_id, share.share_id).await?;
+
+    Ok(Json(saved_slices(&task).await?))
+}
+
+// Existing files are never replaced by visitors, the new one is renamed instead.
+#[post("/share/<slug>/finish-upload/<uuid>")]
+async fn share_finish_upload(
+    state: &State<AppState>,
+    slug: &str,
+    uuid: &str,
+    audit: Auditor<'_>,
+) -> Result<Json<UploadResultResponse>, Error> {
+    audit.log(0, AuditAction::FinishUpload, "", uuid);
+
+    let (share, _) = find_upload_share(state, slug).await?;
+    let task = find_task(state, uuid, share.user_id, share.share_id).await?;
+    audit.log(0, AuditAction::FinishUpload, &share.path, &task.filename);
+
+    let saved_file = complete_upload(state, &task).await?;
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    Share::increase_upload_query(share.share_id, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(upload_result(&task, saved_file)))
+}
+
+// A whole file in the body of a single request, for scripts like `curl -T`. The body is
+// streamed to a part file next to the target, which is synced and moved into place only
+// when complete. The conflict policy is the same as for `pre_upload`, and the optional
+// hash is verified in the format of `FileHash`.
+#[put("/file/<path>?<options..>", data = "<data>")]
+async fn put_file(
+    state: &State<AppState>,
+    path: &str,
+    options: PutFileOptions,
+    data: Data<'_>,
+    content_length: ContentLength,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<Json<PutFileResponse>, Error> {
+    audit.log(user.uid, AuditAction::PutFile, path, "");
+
+    let access = AccessControl::load(state, &user).await?;
+    let target_path = access.resolve(path).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+    let filename = target_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or(Error::BadRequest)?;
+    let target_dir = target_path.parent().ok_or(Error::BadRequest)?;
+    if target_path == access.root() || !target_dir.is_dir() {
+        return Err(Error::BadRequest);
+    }
+
+    if !access.can(target_dir, Access::Write) {
+        return Err(Error::Forbidden);
+    }
+
+    let conflict = match options.conflict.as_deref() {
+        Some(name) => ConflictPolicy::from_name(name).ok_or(Error::BadRequest)?,
+        None => ConflictPolicy::Fail,
+    };
+    if conflict == ConflictPolicy::Fail || conflict == ConflictPolicy::Overwrite {
+        conflict.resolve(target_dir, &filename)?;
+    }
+
+    let declared_hash = match options.hash.as_deref() {
+        Some(hash) => Some(FileHash::parse(hash).ok_or(Error::BadRequest)?),
+        None => None,
+    };
+
+    // A chunked body is checked against the quota after it is received.
     let storage = state.get_site()?.storage.clone();
-    let target_dir = PathBuf::from(&storage).join(&util::parse_encoded_url(&req_body.target)?);
-    if !target_dir.exists() || !target_dir.is_dir() {
+    let available_space = util::file_system::get_available_space(&storage);
+    let mut conn = state.get_pool_conn().await?;
+    if let Some(length) = content_length.length {
+        if available_space > 0 && available_space < length {
+            return Err(Error::BadRequest);
+        }
+
+        if quota::exceeds_quota(
+            Path::new(&storage),
+            user.uid,
+            target_dir,
+            length,
+            None,
+            &mut conn,
+        )
+        .await?
+        {
+            return Err(Error::InsufficientStorage);
+        }
+    }
+
+    let algorithm = declared_hash
+        .as_ref()
+        .map(|h| h.algorithm)
+        .unwrap_or(HashAlgorithm::Sha256);
+    let limit = match (content_length.length, available_space) {
+        (Some(length), _) => length,
+        (None, 0) => u64::MAX,
+        (None, space) => space,
+    };
+    let part_file = target_dir.join(format!(".{}.part", uuid::Uuid::new_v4()));
+    let (size, digest) = match write_body(data, limit, &part_file, algorithm).await {
+        Ok(written) => written,
+        Err(e) => {
+            eprintln!("Error when writing request body: {}", e);
+            if part_file.exists() {
+                fs::remove_file(&part_file).await?;
+            }
+            return Err(Error::BadRequest);
+        }
+    };
+
+    // The client may be gone before sending the whole body.
+    if matches!(content_length.length, Some(length) if length != size) {
+        fs::remove_file(&part_file).await?;
         return Err(Error::BadRequest);
     }
 
+    if content_length.length.is_none()
+        && quota::exceeds_quota(
+            Path::new(&storage),
+            user.uid,
+            target_dir,
+            size,
+            None,
+            &mut conn,
+        )
+        .await?
+    {
+        fs::remove_file(&part_file).await?;
+        return Err(Error::InsufficientStorage);
+    }
+
+    if let Some(declared_hash) = declared_hash {
+        if declared_hash.digest != digest {
+            fs::remove_file(&part_file).await?;
+            return Err(Error::UnprocessableEntity);
+        }
+    }
+
+    let hash = FileHash { algorithm, digest }.to_string();
+    let saved_file = match conflict.resolve(target_dir, &filename) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            fs::remove_file(&part_file).await?;
+            return Ok(Json(PutFileResponse {
+                filename,
+                size,
+                hash,
+                skipped: true,
+            }));
+        }
+        Err(e) => {
+            fs::remove_file(&part_file).await?;
+            return Err(e);
+        }
+    };
+
+    fs::rename(&part_file, &saved_file).await?;
+    record_uploaded_file(&storage, &saved_file, user.uid, size as i64, &mut conn).await?;
+
+    Ok(Json(PutFileResponse {
+        filename: saved_file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or(filename),
+        size,
+        hash,
+        skipped: false,
+    }))
+}
+
+// Return the size and the digest of the body, which must end within the limit.
+pub async fn write_body(
+    data: Data<'_>,
+    limit: u64,
+    part_file: &Path,
+    algorithm: HashAlgorithm,
+) -> AnyResult<(u64, Vec<u8>)> {
+    // One more byte is read to tell a body too large from one of the exact limit.
+    let mut stream = data.open(limit.saturating_add(1).bytes());
+    let mut file = File::create(part_file).await?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0; COMBINE_BUFFER_SIZE];
+    let mut size = 0;
+
+    loop {
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+
+        size += n as u64;
+        if size > limit {
+            return Err(anyhow::anyhow!("Request body too large"));
+        }
+
+        file.write_all(&buffer[..n]).await?;
+        hasher.update(&buffer[..n]);
+    }
+    file.sync_all().await?;
+
+    Ok((size, hasher.finalize()))
+}
+
+// Shared by the slice and tus protocols. The target in the request is relative to the
+// root of the user.
+pub async fn start_user_upload(
+    state: &AppState,
+    req: &UploadRequest,
+    user: &AuthUser,
+) -> Result<String, Error> {
+    let access = AccessControl::load(state, user).await?;
+    let target_dir = access.resolve(&req.target).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+
+    if !access.can(&target_dir, Access::Write) {
+        return Err(Error::Forbidden);
+    }
+
+    let target_dir = match req.relative_dir.as_deref() {
+        Some(relative_dir) if !relative_dir.is_empty() => {
+            let dir = access::resolve_under(&target_dir, Path::new(relative_dir)).map_err(|e| {
+                eprintln!("{}", e);
+                400
+            })?;
+            if !access.can(&dir, Access::Write) {
+                return Err(Error::Forbidden);
+            }
+
+            create_dir_under(&target_dir, &dir).await?
+        }
+        _ => target_dir,
+    };
+
+    let conflict = req.conflict.unwrap_or(ConflictPolicy::Fail);
+    start_upload(state, req, user.uid, 0, target_dir, conflict).await
+}
+
+async fn start_upload(
+    state: &AppState,
+    req: &UploadRequest,
+    userid: i64,
+    share_id: i64,
+    target_dir: PathBuf,
+    conflict: ConflictPolicy,
+) -> Result<String, Error> {
+    if !util::is_valid_filename(&req.filename) || !target_dir.is_dir() {
+        return Err(Error::BadRequest);
+    }
+
+    // Fail early rather than after all the slices are sent.
+    if conflict == ConflictPolicy::Fail || conflict == ConflictPolicy::Overwrite {
+        conflict.resolve(&target_dir, &req.filename)?;
+    }
+
+    // An empty hash skips the verification, but an unknown one would always fail it.
+    if !req.hash.is_empty() && FileHash::parse(&req.hash).is_none() {
+        return Err(Error::BadRequest);
+    }
+
+    let storage = state.get_site()?.storage.clone();
     let available_space = util::file_system::get_available_space(&storage);
-    if available_space > 0 && available_space < req_body.size {
+    if available_space > 0 && available_space < req.size {
         return Err(Error::BadRequest);
     }
 
-    let upload_task = UploadTask::new(&req_body, user.uid, target_dir);
-    let uuid = upload_task.uuid.clone();
-    let temp_upload_dir = PathBuf::from(util::get_temp_path()).join(&upload_task.uuid);
-    fs::create_dir_all(temp_upload_dir).await?;
-    state.push_upload_task(upload_task)?;
+    let mut conn = state.get_pool_conn().await?;
+    if quota::exceeds_quota(
+        Path::new(&storage),
+        userid,
+        &target_dir,
+        req.size,
+        None,
+        &mut conn,
+    )
+    .await?
+    {
+        return Err(Error::InsufficientStorage);
+    }
+
+    let upload_task = UploadTask::new(req, userid, share_id, &target_dir, conflict);
+    fs::create_dir_all(upload_task.temp_dir()).await?;
+    let mut tx = conn.begin().await?;
+    upload_task.insert_query(&mut tx).await?;
+    tx.commit().await?;
 
-    Ok(uuid)
+    Ok(upload_task.uuid)
 }
 
-#[post("/upload/<uuid>/<index>", data = "<file>")]
-async fn upload_file_slices(
-    state: &State<AppState>,
+// Create the missing dirs one by one, refusing any existing file in the way, and any
+// link which leads out of the root.
+async fn create_dir_under(root: &Path, dir: &Path) -> Result<PathBuf, Error> {
+    let relative = dir.strip_prefix(root).map_err(|_| Error::BadRequest)?;
+    let canonical_root = fs::canonicalize(root).await?;
+
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        if !current.exists() {
+            fs::create_dir(&current).await?;
+        } else if !current.is_dir() {
+            return Err(Error::Conflict);
+        } else if !fs::canonicalize(&current)
+            .await?
+            .starts_with(&canonical_root)
+        {
+            return Err(Error::BadRequest);
+        }
+    }
+
+    Ok(current)
+}
+
+// Tasks of upload shares are never reachable from the endpoints for users, and vice versa.
+pub async fn find_task(
+    state: &AppState,
     uuid: &str,
+    userid: i64,
+    share_id: i64,
+) -> Result<UploadTask, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let task = UploadTask::find_by_uuid(uuid, &mut conn)
+        .await?
+        .ok_or(Error::BadRequest)?;
+    if task.userid != userid || task.share_id != share_id {
+        return Err(Error::Unauthorized);
+    }
+
+    Ok(task)
+}
+
+// The slice is written to a part file first, so a broken request, or one not matching
+// its checksum, never leaves a partial slice behind to be combined later.
+async fn save_file_slice(
+    state: &AppState,
+    task: &UploadTask,
     index: u64,
     mut file: TempFile<'_>,
-    user: AuthAdmin,
+    checksum: &UploadChecksum,
 ) -> Result<(), Error> {
-    let task = match state.find_upload_uuid(uuid)? {
-        Some(v) => v,
-        None => return Err(Error::BadRequest),
-    };
-
-    if task.userid != user.uid {
-        return Err(Error::Unauthorized);
+    let temp_upload_dir = task.temp_dir();
+    if index == 0 || !temp_upload_dir.exists() || !temp_upload_dir.is_dir() {
+        return Err(Error::BadRequest);
     }
 
-    let temp_upload_dir = PathBuf::from(util::get_temp_path()).join(uuid);
-    if !temp_upload_dir.exists() || !temp_upload_dir.is_dir() {
+    // Every slice holds at least one byte, and together they never exceed the declared
+    // size. A slice sent again replaces the one before.
+    let size = task.size as u64;
+    let slice_size = file.len();
+    if index > size.max(1) || slice_size > size {
         return Err(Error::BadRequest);
     }
 
-    let temp_file = temp_upload_dir.join(index.to_string());
-    if let Err(e) = file.copy_to(temp_file).await {
+    // Slices may arrive in parallel, even the same one retried, so every request has its own part file.
+    let part_file = temp_upload_dir.join(format!("{}.{}.part", index, uuid::Uuid::new_v4()));
+    if let Err(e) = file.copy_to(&part_file).await {
         eprintln!("File slice copy error: {:?}", e);
         return Err(Error::InternalServerError);
     }
 
+    if checksum.checksum.is_some() {
+        let verified = checksum.verify(&fs::read(&part_file).await?);
+        if verified != Some(true) {
+            fs::remove_file(&part_file).await?;
+            return match verified {
+                Some(_) => Err(Error::UnprocessableEntity),
+                None => Err(Error::BadRequest),
+            };
+        }
+    }
+
+    // Held until the slice is counted, so the slices sent at once are checked against the
+    // size and counted one after another.
+    let _lock = state.upload_locks.lock(&task.uuid).await;
+    let saved: u64 = slice_sizes(&temp_upload_dir)
+        .await?
+        .iter()
+        .filter(|(i, _)| *i != index)
+        .map(|(_, size)| size)
+        .sum();
+    if saved + slice_size > size {
+        fs::remove_file(&part_file).await?;
+        return Err(Error::BadRequest);
+    }
+
+    let slice_file = temp_upload_dir.join(index.to_string());
+    let is_new_slice = !slice_file.exists();
+    fs::rename(&part_file, slice_file).await?;
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    if is_new_slice {
+        UploadTask::finish_slice_query(&task.uuid, &mut tx).await?;
+    } else {
+        UploadTask::touch_query(&task.uuid, &mut tx).await?;
+    }
+    tx.commit().await?;
+
     Ok(())
 }
 
-#[post("/finish-upload/<uuid>")]
-async fn finish_upload(state: &State<AppState>, uuid: &str, _user: AuthAdmin) -> Result<(), Error> {
-    let temp_upload_dir = PathBuf::from(util::get_temp_path()).join(uuid);
+async fn saved_slices(task: &UploadTask) -> Result<Vec<u64>, Error> {
+    let temp_upload_dir = task.temp_dir();
+    if !temp_upload_dir.is_dir() {
+        return Err(Error::BadRequest);
+    }
+
+    Ok(slice_indices(&temp_upload_dir).await?)
+}
+
+// The indices of the slices saved in the dir, in order.
+async fn slice_indices(temp_upload_dir: &Path) -> AnyResult<Vec<u64>> {
+    let mut slices = vec![];
+    let mut dir_iterator = fs::read_dir(temp_upload_dir).await?;
+    while let Some(entry) = dir_iterator.next_entry().await? {
+        if let Some(index) = entry.file_name().to_str().and_then(|n| n.parse().ok()) {
+            slices.push(index);
+        }
+    }
+    slices.sort_unstable();
+
+    Ok(slices)
+}
+
+// The indices and the sizes of the slices saved in the dir, in order.
+async fn slice_sizes(temp_upload_dir: &Path) -> AnyResult<Vec<(u64, u64)>> {
+    let mut slices = vec![];
+    for index in slice_indices(temp_upload_dir).await? {
+        let size = fs::metadata(temp_upload_dir.join(index.to_string()))
+            .await?
+            .len();
+        slices.push((index, size));
+    }
+
+    Ok(slices)
+}
+
+// Return the path the file is saved to, or `None` if it is skipped for a conflict.
+pub async fn complete_upload(
+    state: &AppState,
+    task: &UploadTask,
+) -> Result<Option<PathBuf>, Error> {
+    let temp_upload_dir = task.temp_dir();
     if !temp_upload_dir.exists() || !temp_upload_dir.is_dir() {
         return Err(Error::BadRequest);
     }
 
-    let task = state.find_upload_uuid(uuid)?.unwrap();
-    let target_dir = &task.dir;
+    let target_dir = &task.target_dir();
     if !target_dir.exists() || target_dir.is_file() {
         return Err(Error::BadRequest);
     }
 
-    let target_file_path = target_dir.join(&task.filename);
-    if target_file_path.exists() {
-        fs::remove_file(&target_file_path).await?;
+    // Slices may be sent in any order, but none of them may be missing.
+    let slices = slice_sizes(&temp_upload_dir).await?;
+    let missing = missing_slices(&slices, task.size as u64);
+    if !missing.is_empty() {
+        return Err(Error::MissingSlices(missing));
     }
 
-    if let Err(e) = combine_file_slices(&target_file_path, &temp_upload_dir, task.size).await {
-        eprintln!("Error when combining file slices: {}", e);
-        fs::remove_file(&target_file_path).await?;
-        return Err(Error::InternalServerError);
+    if slices.iter().map(|(_, size)| size).sum::<u64>() != task.size as u64 {
+        return Err(Error::BadRequest);
     }
 
+    // Resolved again, as the file may be created by others since the upload started.
+    let target_file_path = match task.conflict_policy().resolve(target_dir, &task.filename)? {
+        Some(path) => path,
+        None => {
+            let mut conn = state.get_pool_conn().await?;
+            fs::remove_dir_all(&temp_upload_dir).await?;
+            let mut tx = conn.begin().await?;
+            UploadTask::delete_query(&task.uuid, &mut tx).await?;
+            tx.commit().await?;
+            return Ok(None);
+        }
+    };
+
+    // Check again, as other uploads may have finished since this one started.
+    let storage = state.get_site()?.storage.clone();
+    let mut conn = state.get_pool_conn().await?;
+    if quota::exceeds_quota(
+        Path::new(&storage),
+        task.userid,
+        target_dir,
+        task.size as u64,
+        None,
+        &mut conn,
+    )
+    .await?
+    {
+        return Err(Error::InsufficientStorage);
+    }
+
+    // The slices are combined next to the target, which is only replaced once the
+    // file is complete and verified.
+    let combined_file_path = target_dir.join(format!(".{}.part", task.uuid));
+    let hash = FileHash::parse(&task.hash);
+    let digest = match combine_file_slices(
+        &combined_file_path,
+        &temp_upload_dir,
+        task.size as u64,
+        hash.as_ref().map(|h| h.algorithm),
+    )
+    .await
+    {
+        Ok(digest) => digest,
+        Err(e) => {
+            eprintln!("Error when combining file slices: {}", e);
+            if combined_file_path.exists() {
+                fs::remove_file(&combined_file_path).await?;
+            }
+            return Err(Error::InternalServerError);
+        }
+    };
+
+    // The slices are corrupted, so the task is dropped and has to be uploaded again.
+    if let Some(hash) = hash {
+        if digest != Some(hash.digest) {
+            eprintln!("Hash of the uploaded file not match: {}", task.hash);
+            fs::remove_file(&combined_file_path).await?;
+            fs::remove_dir_all(&temp_upload_dir).await?;
+            let mut tx = conn.begin().await?;
+            UploadTask::delete_query(&task.uuid, &mut tx).await?;
+            tx.commit().await?;
+            return Err(Error::UnprocessableEntity);
+        }
+    }
+
+    fs::rename(&combined_file_path, &target_file_path).await?;
     fs::remove_dir_all(&temp_upload_dir).await?;
-    state.remove_upload_task(task)?;
 
-    Ok(())
+    record_uploaded_file(
+        &storage,
+        &target_file_path,
+        task.userid,
+        task.size,
+        &mut conn,
+    )
+    .await?;
+    let mut tx = conn.begin().await?;
+    UploadTask::delete_query(&task.uuid, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Some(target_file_path))
 }
 
-#[post("/cancel-upload", data = "<req_body>")]
-async fn cancel_upload(
-    state: &State<AppState>,
-    req_body: Json<CancelUploadRequest>,
-    user: AuthAdmin,
-) -> Result<(), Error> {
-    for uuid in req_body.uuids.iter() {
-        remove_upload_task(state, uuid, &user).await?;
+fn upload_result(task: &UploadTask, saved_file: Option<PathBuf>) -> UploadResultResponse {
+    let filename = saved_file
+        .as_ref()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| task.filename.to_owned());
+
+    UploadResultResponse {
+        filename,
+        skipped: saved_file.is_none(),
     }
+}
 
-    Ok(())
+async fn find_upload_share(state: &AppState, slug: &str) -> Result<(Share, PathBuf), Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let share = Share::find_by_slug(slug, &mut conn).await?.ok_or(404)?;
+    if !share.is_upload() || !share.is_active(util::get_utc_seconds()) {
+        return Err(Error::NotFound);
+    }
+
+    let storage = state.get_site()?.storage.clone();
+    let target_dir =
+        access::resolve_under(Path::new(&storage), Path::new(&share.path)).map_err(|e| {
+            eprintln!("{}", e);
+            400
+        })?;
+
+    if !target_dir.is_dir() {
+        return Err(Error::NotFound);
+    }
+
+    Ok((share, target_dir))
 }
 
-async fn remove_upload_task(
-    state: &State<AppState>,
-    uuid: &str,
-    user: &AuthAdmin,
-) -> AnyResult<()> {
-    if let Some(task) = state.find_upload_uuid(uuid)? {
-        if task.userid != user.uid {
+pub async fn remove_upload_task(state: &AppState, uuid: &str, user: &AuthUser) -> AnyResult<()> {
+    let mut conn = state.get_pool_conn().await?;
+    if let Some(task) = UploadTask::find_by_uuid(uuid, &mut conn).await? {
+        if task.userid != user.uid || task.share_id != 0 {
             return Err(anyhow::anyhow!("User id not match to remove task"));
         }
 
-        let temp_upload_dir = PathBuf::from(util::get_temp_path()).join(&task.uuid);
+        let temp_upload_dir = task.temp_dir();
         if temp_upload_dir.exists() && temp_upload_dir.is_dir() {
             fs::remove_dir_all(temp_upload_dir).await?;
         }
 
-        state.remove_upload_task(task)?;
+        let mut tx = conn.begin().await?;
+        UploadTask::delete_query(&task.uuid, &mut tx).await?;
+        tx.commit().await?;
     }
 
     Ok(())
 }
 
-async fn combine_file_slices(
-    target_file_path: &PathBuf,
-    temp_upload_dir: &PathBuf,
-    req_file_size: u64,
+// Save the owner of the file for the user quota.
+async fn record_uploaded_file(
+    storage: &str,
+    target_file_path: &Path,
+    userid: i64,
+    size: i64,
+    conn: &mut PoolConnection<Sqlite>,
 ) -> AnyResult<()> {
-    let mut target_file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(&target_file_path)
-        .await?;
+    let relative_path = target_file_path.strip_prefix(storage)?;
+    let uploaded = UploadedFile::new(&relative_path.to_string_lossy(), userid, size);
 
-    let mut index: u64 = 1; // Change to start index from 1.
-    let mut file_slice = temp_upload_dir.join(index.to_string());
+    let mut tx = conn.begin().await?;
+    uploaded.upsert_query(&mut tx).await?;
+    tx.commit().await?;
 
-    while file_slice.exists() {
-        let mut buffer = vec![];
-        let mut source_file = File::open(&file_slice).await?;
-        source_file.read_to_end(&mut buffer).await?;
-        target_file.write_all(&buffer).await?;
+    Ok(())
+}
+
+// The slices are copied in chunks rather than read in full. Return the digest of the
+// combined file, if an algorithm is given.
+async fn combine_file_slices(
+    target_file_path: &Path,
+    temp_upload_dir: &Path,
+    req_file_size: u64,
+    algorithm: Option<HashAlgorithm>,
+) -> AnyResult<Option<Vec<u8>>> {
+    let slices = slice_indices(temp_upload_dir).await?;
+    let mut target_file = File::create(target_file_path).await?;
+    let mut hasher = algorithm.map(Hasher::new);
+    let mut buffer = vec![0; COMBINE_BUFFER_SIZE];
 
-        index += 1;
-        file_slice = temp_upload_dir.join(index.to_string());
+    for index in slices {
+        let mut source_file = File::open(temp_upload_dir.join(index.to_string())).await?;
+        loop {
+            let n = source_file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+
+            target_file.write_all(&buffer[..n]).await?;
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(&buffer[..n]);
+            }
+        }
     }
+    target_file.flush().await?;
 
-    let filesize = fs::metadata(&target_file_path).await?.len();
+    let filesize = fs::metadata(target_file_path).await?.len();
     if filesize != req_file_size {
         return Err(anyhow::anyhow!("File size not match"));
     }
 
-    Ok(())
+    Ok(hasher.map(|h| h.finalize()))
+}
+
+// The indices missing from 1 to the last slice, given the indices and sizes of the saved
+// ones. All slices but the last are of the same size, so while the saved ones add up to
+// less than the file, the rest are counted at the size of the largest one.
+fn missing_slices(slices: &[(u64, u64)], file_size: u64) -> Vec<u64> {
+    let last = slices.iter().map(|(index, _)| *index).max().unwrap_or(0);
+    let saved: u64 = slices.iter().map(|(_, size)| *size).sum();
+    let expected = match slices.iter().map(|(_, size)| *size).max() {
+        Some(slice_size) if saved < file_size && slice_size > 0 => {
+            file_size.div_ceil(slice_size).max(last)
+        }
+        _ => last,
+    };
+
+    let mut missing: Vec<u64> = (1..=expected)
+        .filter(|i| !slices.iter().any(|(index, _)| index == i))
+        .collect();
+    if missing.is_empty() && saved < file_size {
+        missing.push(expected + 1);
+    }
+
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_slices() {
+        assert!(missing_slices(&[], 0).is_empty());
+        assert!(missing_slices(&[(1, 10), (2, 10), (3, 5)], 25).is_empty());
+        assert_eq!(missing_slices(&[(1, 10), (3, 5)], 25), vec![2]);
+        assert_eq!(missing_slices(&[(2, 10), (3, 5)], 25), vec![1]);
+        assert_eq!(missing_slices(&[(1, 10), (2, 10)], 25), vec![3]);
+        assert_eq!(missing_slices(&[(1, 10)], 35), vec![2, 3, 4]);
+        assert_eq!(missing_slices(&[], 10), vec![1]);
+    }
 }
@@ -1,19 +1,43 @@
+use crate::entity::acl::{Acl, AclSubject};
+use crate::entity::api_token::ApiToken;
+use crate::entity::audit::AuditAction;
 use crate::entity::error::Error;
+use crate::entity::group::GroupMember;
+use crate::entity::job::Job;
+use crate::entity::login_attempt::LoginAttempt;
+use crate::entity::quota::UploadedFile;
+use crate::entity::recovery_code::RecoveryCode;
 use crate::entity::request::{
-    ChangePasswordRequest, ForgotPasswordRequest, LoginRequest, ResetPasswordRequest,
+    ChangePasswordRequest, CreateApiTokenRequest, CreateUserRequest, DisableTwoFactorRequest,
+    ForgotPasswordRequest, LoginRequest, ResetPasswordRequest, TwoFactorCodeRequest,
+    UpdateHomeDirRequest, UpdatePermissionRequest, UpdateUserStatusRequest,
 };
 use crate::entity::reset_password::ResetPassword;
-use crate::entity::response::LoginResponse;
+use crate::entity::response::{
+    ApiTokenResponse, LoginResponse, SessionResponse, TwoFactorSetupResponse,
+    TwoFactorStatusResponse, TwoFactorStep, UserResponse,
+};
+use crate::entity::session::Session;
+use crate::entity::share::Share;
+use crate::entity::share_access::ShareAccess;
+use crate::entity::upload_task::UploadTask;
 use crate::entity::user::User;
+use crate::service::access;
 use crate::service::app_state::AppState;
-use crate::service::auth::AuthUser;
-use crate::service::token::{AccessToken, RefreshToken, Token};
+use crate::service::audit::Auditor;
+use crate::service::auth::{AuthAdmin, AuthUser, TwoFactorUser};
+use crate::service::client::ClientInfo;
+use crate::service::token::{AccessToken, RefreshToken, Token, TwoFactorToken};
+use crate::service::totp;
 use crate::util::{self, constants::*};
 use anyhow::Result as AnyResult;
 use rocket::http::{Cookie, CookieJar};
 use rocket::serde::json::Json;
+use rocket::tokio::fs;
 use rocket::{Route, State};
-use sqlx::Connection;
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, Sqlite};
+use std::path::Path;
 
 pub fn route() -> Vec<Route> {
     routes![
@@ -23,7 +47,24 @@ pub fn route() -> Vec<Route> {
         refresh_access_token,
         guest_login,
         forgot_password,
-        reset_password
+        reset_password,
+        list_users,
+        create_user,
+        update_user_permission,
+        update_user_status,
+        update_user_home_dir,
+        delete_user,
+        list_api_tokens,
+        create_api_token,
+        revoke_api_token,
+        list_sessions,
+        revoke_session,
+        revoke_all_sessions,
+        login_two_factor,
+        two_factor_status,
+        setup_two_factor,
+        enable_two_factor,
+        disable_two_factor
     ]
 }
 
@@ -31,23 +72,109 @@ pub fn route() -> Vec<Route> {
 async fn login(
     state: &State<AppState>,
     req_body: Json<LoginRequest>,
+    client: ClientInfo,
     jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
 ) -> Result<Json<LoginResponse>, Error> {
+    audit.log(0, AuditAction::Login, "", &req_body.username);
+
     if req_body.username.len() < 2 || req_body.password.len() < 6 {
         return Err(Error::BadRequest);
     }
 
     let mut conn = state.get_pool_conn().await?;
-    let user = User::login(&req_body.username, &req_body.password, &mut conn).await?;
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::user_key(&req_body.username),
+    ];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
+    let user = match User::login(&req_body.username, &req_body.password, &mut conn).await {
+        Ok(user) => user,
+        Err(e) => {
+            eprintln!("Login failed: {}", e);
+            return Err(Error::Unauthorized);
+        }
+    };
+
+    // The attempt still counts for the user until the second factor is right.
+    audit.log(user.user_id, AuditAction::Login, "", &user.username);
+    LoginAttempt::take_back(&[LoginAttempt::ip_key(&client.ip)], &mut conn).await?;
+    let secret = state.get_secret()?;
+
+    if let Some(step) = get_two_factor_step(state, &user)? {
+        set_two_factor_token(&user, &secret, jar)?;
+
+        let login = LoginResponse {
+            username: user.username,
+            permission: user.permission,
+            expire: 0,
+            two_factor: Some(step),
+        };
+
+        return Ok(Json(login));
+    }
+
+    clear_attempts(&LoginAttempt::user_key(&user.username), &mut conn).await?;
+    let session = create_session(&user, &client, &mut conn).await?;
+    let access_token = set_access_token(&user, &secret, jar)?;
+    set_refresh_token(&user, &session.session_id, &secret, jar)?;
+
+    let login = LoginResponse {
+        username: user.username,
+        permission: user.permission,
+        expire: access_token.exp,
+        two_factor: None,
+    };
+
+    Ok(Json(login))
+}
+
+#[post("/login/2fa", data = "<req_body>")]
+async fn login_two_factor(
+    state: &State<AppState>,
+    token: TwoFactorToken,
+    req_body: Json<TwoFactorCodeRequest>,
+    client: ClientInfo,
+    jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
+) -> Result<Json<LoginResponse>, Error> {
+    audit.log(token.uid, AuditAction::LoginTwoFactor, "", "");
+
+    let mut conn = state.get_pool_conn().await?;
+    let user = match User::find_user_by_id(token.uid, &mut conn).await? {
+        Some(user) => user,
+        None => return Err(Error::Unauthorized),
+    };
+
+    if user.is_disabled() || !user.is_totp_enabled() {
+        return Err(Error::BadRequest);
+    }
+
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::user_key(&user.username),
+    ];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
     let secret = state.get_secret()?;
+    if !verify_two_factor_code(&user, &req_body.code, &secret, &mut conn).await? {
+        return Err(Error::Unauthorized);
+    }
+
+    LoginAttempt::take_back(&[LoginAttempt::ip_key(&client.ip)], &mut conn).await?;
+    clear_attempts(&LoginAttempt::user_key(&user.username), &mut conn).await?;
 
+    remove_two_factor_token(jar);
+    let session = create_session(&user, &client, &mut conn).await?;
     let access_token = set_access_token(&user, &secret, jar)?;
-    set_refresh_token(&user, &secret, jar)?;
+    set_refresh_token(&user, &session.session_id, &secret, jar)?;
 
     let login = LoginResponse {
         username: user.username,
         permission: user.permission,
         expire: access_token.exp,
+        two_factor: None,
     };
 
     Ok(Json(login))
@@ -56,24 +183,35 @@ async fn login(
 #[get("/login/guest")]
 async fn guest_login(
     state: &State<AppState>,
+    client: ClientInfo,
     jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
 ) -> Result<Json<LoginResponse>, Error> {
+    audit.log(0, AuditAction::GuestLogin, "", "");
+
+    let mut conn = state.get_pool_conn().await?;
+    let attempt_keys = vec![LoginAttempt::ip_key(&client.ip)];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
     let allow_guest = state.get_site()?.allow_guest;
     if allow_guest <= 0 {
         return Err(Error::BadRequest);
     }
+    LoginAttempt::take_back(&attempt_keys, &mut conn).await?;
 
     let current_timestamp = util::get_utc_seconds();
     let user = User::guest(current_timestamp);
     let secret = state.get_secret()?;
 
+    let session = create_session(&user, &client, &mut conn).await?;
     let access_token = set_access_token(&user, &secret, jar)?;
-    set_refresh_token(&user, &secret, jar)?;
+    set_refresh_token(&user, &session.session_id, &secret, jar)?;
 
     let login = LoginResponse {
         username: user.username,
         permission: user.permission,
         expire: access_token.exp,
+        two_factor: None,
     };
 
     Ok(Json(login))
@@ -82,16 +220,47 @@ async fn guest_login(
 #[put("/user/password", data = "<req_body>")]
 async fn change_password(
     state: &State<AppState>,
-    _user: AuthUser,
+    user: AuthUser,
     req_body: Json<ChangePasswordRequest>,
+    client: ClientInfo,
     jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
 ) -> Result<(), Error> {
+    audit.log(
+        user.uid,
+        AuditAction::ChangePassword,
+        "",
+        &req_body.username,
+    );
+
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
     let mut conn = state.get_pool_conn().await?;
-    let mut user = User::login(&req_body.username, &req_body.old_password, &mut conn).await?;
+    let current = User::find_user_by_id(user.uid, &mut conn)
+        .await?
+        .ok_or(404)?;
+    if current.username != req_body.username {
+        return Err(Error::Forbidden);
+    }
+
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::user_key(&current.username),
+    ];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
+    let mut user = User::login(&current.username, &req_body.old_password, &mut conn)
+        .await
+        .map_err(|_| Error::Unauthorized)?;
+    LoginAttempt::take_back(&attempt_keys, &mut conn).await?;
     user.password = req_body.new_password.clone();
 
+    // The current session is signed out as well, so every session of the user ends here.
     let mut tx = conn.begin().await?;
     user.update(&mut tx).await?;
+    Session::delete_user_sessions_query(user.user_id, &mut tx).await?;
     tx.commit().await?;
     remove_tokens(jar);
 
@@ -99,7 +268,22 @@ async fn change_password(
 }
 
 #[get("/user/signout")]
-async fn signout(_user: AuthUser, jar: &CookieJar<'_>) -> Result<(), Error> {
+async fn signout(
+    state: &State<AppState>,
+    user: AuthUser,
+    token: Option<RefreshToken>,
+    jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(user.uid, AuditAction::Signout, "", "");
+
+    if let Some(token) = token {
+        let mut conn = state.get_pool_conn().await?;
+        let mut tx = conn.begin().await?;
+        Session::delete_query(&token.sid, token.uid, &mut tx).await?;
+        tx.commit().await?;
+    }
+
     remove_tokens(jar);
 
     Ok(())
@@ -109,9 +293,18 @@ async fn signout(_user: AuthUser, jar: &CookieJar<'_>) -> Result<(), Error> {
 async fn refresh_access_token(
     state: &State<AppState>,
     token: RefreshToken,
+    client: ClientInfo,
     jar: &CookieJar<'_>,
 ) -> Result<Json<LoginResponse>, Error> {
     let mut conn = state.get_pool_conn().await?;
+    if Session::find_valid(&token.sid, token.uid, &mut conn)
+        .await?
+        .is_none()
+    {
+        remove_tokens(jar);
+        return Err(Error::Unauthorized);
+    }
+
     let user: User;
 
     if state.get_allow_guest()? > 0 && token.uid == 0 {
@@ -119,20 +312,25 @@ async fn refresh_access_token(
     } else {
         let user_op = User::find_user_by_id(token.uid, &mut conn).await?;
 
-        if user_op.is_none() {
+        if user_op.is_none() || user_op.as_ref().unwrap().is_disabled() {
             return Err(Error::BadRequest);
         }
         user = user_op.unwrap();
     }
 
+    let mut tx = conn.begin().await?;
+    Session::touch_query(&token.sid, &client, &mut tx).await?;
+    tx.commit().await?;
+
     let secret = state.get_secret()?;
     let access_token = set_access_token(&user, &secret, jar)?;
-    set_refresh_token(&user, &secret, jar)?;
+    set_refresh_token(&user, &token.sid, &secret, jar)?;
 
     let login = LoginResponse {
         username: user.username,
         permission: user.permission,
         expire: access_token.exp,
+        two_factor: None,
     };
 
     Ok(Json(login))
@@ -142,12 +340,23 @@ async fn refresh_access_token(
 async fn forgot_password(
     state: &State<AppState>,
     req_body: Json<ForgotPasswordRequest>,
+    client: ClientInfo,
+    audit: Auditor<'_>,
 ) -> Result<String, Error> {
+    audit.log(0, AuditAction::ForgotPassword, "", &req_body.username);
+
     let mut conn = state.get_pool_conn().await?;
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::user_key(&req_body.username),
+    ];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
     let user = match User::find_user_by_name(&req_body.username, &mut conn).await? {
         Some(user) => user,
         None => return Err(Error::BadRequest),
     };
+    LoginAttempt::take_back(&attempt_keys, &mut conn).await?;
 
     let reset_pw = ResetPassword::new(&user.username);
     // Remove all reset password files belong to this user.
@@ -167,16 +376,30 @@ async fn forgot_password(
 async fn reset_password(
     state: &State<AppState>,
     req_body: Json<ResetPasswordRequest>,
+    client: ClientInfo,
+    audit: Auditor<'_>,
 ) -> Result<(), Error> {
+    audit.log(0, AuditAction::ResetPassword, "", &req_body.username);
+
     let mut conn = state.get_pool_conn().await?;
-    let reset_pw = ResetPassword::from_reset_req(
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::user_key(&req_body.username),
+    ];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
+    let reset_pw = match ResetPassword::from_reset_req(
         &req_body.uuid,
         &req_body.username,
         &req_body.code,
         &mut conn,
     )
     .await
-    .map_err(|_| Error::BadRequest)?;
+    {
+        Ok(reset_pw) => reset_pw,
+        Err(_) => return Err(Error::BadRequest),
+    };
+    LoginAttempt::take_back(&[LoginAttempt::ip_key(&client.ip)], &mut conn).await?;
 
     let mut user = match User::find_user_by_name(&req_body.username, &mut conn).await? {
         Some(user) => user,
@@ -189,11 +412,573 @@ async fn reset_password(
     let mut tx = conn.begin().await?;
     user.update(&mut tx).await?;
     ResetPassword::delete_query(&user.username, &mut tx).await?;
+    Session::delete_user_sessions_query(user.user_id, &mut tx).await?;
+    LoginAttempt::delete_query(&LoginAttempt::user_key(&user.username), &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[get("/users")]
+async fn list_users(
+    state: &State<AppState>,
+    _admin: AuthAdmin,
+) -> Result<Json<Vec<UserResponse>>, Error> {
+    let mut conn = state.get_pool_conn().await?;
+    let users = User::find_all(&mut conn).await?;
+
+    Ok(Json(users.into_iter().map(UserResponse::from).collect()))
+}
+
+#[post("/users", data = "<req_body>")]
+async fn create_user(
+    state: &State<AppState>,
+    req_body: Json<CreateUserRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<Json<UserResponse>, Error> {
+    audit.log(admin.uid, AuditAction::CreateUser, "", &req_body.username);
+
+    if req_body.username.len() < 2
+        || req_body.password.len() < 6
+        || !valid_permission(req_body.permission)
+    {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    if User::find_user_by_name(&req_body.username, &mut conn)
+        .await?
+        .is_some()
+    {
+        return Err(Error::Conflict);
+    }
+
+    let mut user = User::from_create_req(&req_body, util::get_utc_seconds());
+    let mut tx = conn.begin().await?;
+    user.user_id = user.insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(UserResponse::from(user)))
+}
+
+#[put("/users/<uid>/permission", data = "<req_body>")]
+async fn update_user_permission(
+    state: &State<AppState>,
+    uid: i64,
+    req_body: Json<UpdatePermissionRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::UpdatePermission,
+        "",
+        &uid.to_string(),
+    );
+
+    // Admins cannot downgrade themselves to avoid locking out of the site.
+    if uid == admin.uid || !valid_permission(req_body.permission) {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    if User::find_user_by_id(uid, &mut conn).await?.is_none() {
+        return Err(Error::NotFound);
+    }
+
+    let mut tx = conn.begin().await?;
+    User::update_permission_query(uid, req_body.permission, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[put("/users/<uid>/status", data = "<req_body>")]
+async fn update_user_status(
+    state: &State<AppState>,
+    uid: i64,
+    req_body: Json<UpdateUserStatusRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::UpdateUserStatus,
+        "",
+        &uid.to_string(),
+    );
+
+    if uid == admin.uid {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    if User::find_user_by_id(uid, &mut conn).await?.is_none() {
+        return Err(Error::NotFound);
+    }
+
+    let mut tx = conn.begin().await?;
+    User::update_disabled_query(uid, req_body.disabled, &mut tx).await?;
+    if req_body.disabled {
+        Session::delete_user_sessions_query(uid, &mut tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// An empty home directory lets the user browse the whole storage again.
+#[put("/users/<uid>/home", data = "<req_body>")]
+async fn update_user_home_dir(
+    state: &State<AppState>,
+    uid: i64,
+    req_body: Json<UpdateHomeDirRequest>,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        admin.uid,
+        AuditAction::UpdateHomeDir,
+        &req_body.home_dir,
+        &uid.to_string(),
+    );
+
+    if uid == admin.uid {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    if User::find_user_by_id(uid, &mut conn).await?.is_none() {
+        return Err(Error::NotFound);
+    }
+
+    // Resolving under an empty root normalizes the relative path.
+    let decoded = util::parse_encoded_url(&req_body.home_dir)?;
+    let home_dir = access::resolve_under(Path::new(""), &decoded).map_err(|e| {
+        eprintln!("{}", e);
+        400
+    })?;
+
+    let storage = state.get_site()?.storage.clone();
+    if !Path::new(&storage).join(&home_dir).is_dir() {
+        return Err(Error::BadRequest);
+    }
+
+    let home_dir_str = home_dir.to_str().ok_or(400)?;
+    let mut tx = conn.begin().await?;
+    User::update_home_dir_query(uid, home_dir_str, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[delete("/users/<uid>")]
+async fn delete_user(
+    state: &State<AppState>,
+    uid: i64,
+    admin: AuthAdmin,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(admin.uid, AuditAction::DeleteUser, "", &uid.to_string());
+
+    if uid == admin.uid {
+        return Err(Error::BadRequest);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let user = match User::find_user_by_id(uid, &mut conn).await? {
+        Some(user) => user,
+        None => return Err(Error::NotFound),
+    };
+
+    ResetPassword::remove_reset_password_files_by_username(&user.username, &mut conn).await?;
+    let upload_tasks = UploadTask::find_user_tasks(user.user_id, &mut conn).await?;
+    let mut tx = conn.begin().await?;
+    ResetPassword::delete_query(&user.username, &mut tx).await?;
+    ApiToken::delete_user_tokens_query(user.user_id, &mut tx).await?;
+    Session::delete_user_sessions_query(user.user_id, &mut tx).await?;
+    RecoveryCode::delete_user_codes_query(user.user_id, &mut tx).await?;
+    Acl::delete_subject_query(AclSubject::User, user.user_id, &mut tx).await?;
+    GroupMember::delete_user_memberships_query(user.user_id, &mut tx).await?;
+    UploadedFile::delete_user_files_query(user.user_id, &mut tx).await?;
+    Share::delete_user_shares_query(user.user_id, &mut tx).await?;
+    ShareAccess::delete_orphans_query(&mut tx).await?;
+    UploadTask::delete_user_tasks_query(user.user_id, &mut tx).await?;
+    Job::delete_user_jobs_query(user.user_id, &mut tx).await?;
+    User::delete_query(user.user_id, &mut tx).await?;
+    tx.commit().await?;
+
+    for task in upload_tasks.iter() {
+        let temp_upload_dir = task.temp_dir();
+        if temp_upload_dir.exists() && temp_upload_dir.is_dir() {
+            fs::remove_dir_all(temp_upload_dir).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[get("/user/tokens")]
+async fn list_api_tokens(
+    state: &State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<ApiTokenResponse>>, Error> {
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let tokens = ApiToken::find_user_tokens(user.uid, &mut conn).await?;
+
+    Ok(Json(
+        tokens.into_iter().map(ApiTokenResponse::from).collect(),
+    ))
+}
+
+#[post("/user/tokens", data = "<req_body>")]
+async fn create_api_token(
+    state: &State<AppState>,
+    req_body: Json<CreateApiTokenRequest>,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<Json<ApiTokenResponse>, Error> {
+    audit.log(user.uid, AuditAction::CreateApiToken, "", &req_body.name);
+
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let name = req_body.name.trim();
+    let expire_days = req_body.expire_days.unwrap_or(0);
+    if name.is_empty() || expire_days < 0 {
+        return Err(Error::BadRequest);
+    }
+
+    let expire_at = match expire_days {
+        0 => 0,
+        days => util::get_utc_seconds() + days * 24 * 60 * 60,
+    };
+
+    let secret = state.get_secret()?;
+    let (mut token, plain) = ApiToken::new(user.uid, name, req_body.scope, expire_at, &secret);
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    token.token_id = token.insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    let mut response = ApiTokenResponse::from(token);
+    response.token = Some(plain);
+
+    Ok(Json(response))
+}
+
+#[delete("/user/tokens/<token_id>")]
+async fn revoke_api_token(
+    state: &State<AppState>,
+    token_id: i64,
+    user: AuthUser,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(
+        user.uid,
+        AuditAction::RevokeApiToken,
+        "",
+        &token_id.to_string(),
+    );
+
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    ApiToken::delete_query(token_id, user.uid, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[get("/user/sessions")]
+async fn list_sessions(
+    state: &State<AppState>,
+    user: AuthUser,
+    token: Option<RefreshToken>,
+) -> Result<Json<Vec<SessionResponse>>, Error> {
+    // Guest users share the same uid and cannot manage sessions.
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let current_sid = token.map(|t| t.sid).unwrap_or_default();
+    let mut conn = state.get_pool_conn().await?;
+    let sessions = Session::find_user_sessions(user.uid, &mut conn).await?;
+    let response = sessions
+        .into_iter()
+        .map(|s| {
+            let current = s.session_id == current_sid;
+            SessionResponse::from_session(s, current)
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+#[delete("/user/sessions/<session_id>")]
+async fn revoke_session(
+    state: &State<AppState>,
+    session_id: &str,
+    user: AuthUser,
+    token: Option<RefreshToken>,
+    jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(user.uid, AuditAction::RevokeSession, "", session_id);
+
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    Session::delete_query(session_id, user.uid, &mut tx).await?;
+    tx.commit().await?;
+
+    if token.map(|t| t.sid == session_id).unwrap_or(false) {
+        remove_tokens(jar);
+    }
+
+    Ok(())
+}
+
+#[delete("/user/sessions")]
+async fn revoke_all_sessions(
+    state: &State<AppState>,
+    user: AuthUser,
+    jar: &CookieJar<'_>,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(user.uid, AuditAction::RevokeAllSessions, "", "");
+
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    Session::delete_user_sessions_query(user.uid, &mut tx).await?;
+    tx.commit().await?;
+    remove_tokens(jar);
+
+    Ok(())
+}
+
+#[get("/user/2fa")]
+async fn two_factor_status(
+    state: &State<AppState>,
+    user: AuthUser,
+) -> Result<Json<TwoFactorStatusResponse>, Error> {
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let user = User::find_user_by_id(user.uid, &mut conn)
+        .await?
+        .ok_or(404)?;
+    let recovery_codes_left = RecoveryCode::count_unused(user.user_id, &mut conn).await?;
+
+    Ok(Json(TwoFactorStatusResponse {
+        enabled: user.is_totp_enabled(),
+        required: require_two_factor(state, &user)?,
+        recovery_codes_left,
+    }))
+}
+
+// Generate a new secret for the user to scan, which takes effect after being confirmed.
+#[post("/user/2fa/setup")]
+async fn setup_two_factor(
+    state: &State<AppState>,
+    user: TwoFactorUser,
+    audit: Auditor<'_>,
+) -> Result<Json<TwoFactorSetupResponse>, Error> {
+    audit.log(user.uid, AuditAction::SetupTwoFactor, "", "");
+
+    let mut conn = state.get_pool_conn().await?;
+    let user = User::find_user_by_id(user.uid, &mut conn)
+        .await?
+        .ok_or(404)?;
+    if user.is_totp_enabled() {
+        return Err(Error::Conflict);
+    }
+
+    let secret = totp::generate_secret();
+    let mut tx = conn.begin().await?;
+    User::update_totp_secret_query(user.user_id, &secret, &mut tx).await?;
+    tx.commit().await?;
+
+    let site_name = state.get_site()?.name.clone();
+    let uri = totp::provisioning_uri(&site_name, &user.username, &secret);
+
+    Ok(Json(TwoFactorSetupResponse { secret, uri }))
+}
+
+// Confirm the secret with a valid code and return the recovery codes.
+#[post("/user/2fa/enable", data = "<req_body>")]
+async fn enable_two_factor(
+    state: &State<AppState>,
+    user: TwoFactorUser,
+    req_body: Json<TwoFactorCodeRequest>,
+    audit: Auditor<'_>,
+) -> Result<Json<Vec<String>>, Error> {
+    audit.log(user.uid, AuditAction::EnableTwoFactor, "", "");
+
+    let mut conn = state.get_pool_conn().await?;
+    let user = User::find_user_by_id(user.uid, &mut conn)
+        .await?
+        .ok_or(404)?;
+    if user.is_totp_enabled() {
+        return Err(Error::Conflict);
+    }
+
+    if user.totp_secret.is_empty() {
+        return Err(Error::BadRequest);
+    }
+
+    let timestamp = util::get_utc_seconds();
+    let step = match totp::verify(&user.totp_secret, &req_body.code, timestamp, 0)? {
+        Some(step) => step,
+        None => return Err(Error::BadRequest),
+    };
+
+    let secret = state.get_secret()?;
+    let mut tx = conn.begin().await?;
+    User::update_totp_enabled_query(user.user_id, true, &mut tx).await?;
+    User::update_totp_last_step_query(user.user_id, step, &mut tx).await?;
+    let codes = RecoveryCode::regenerate_query(user.user_id, &secret, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Json(codes))
+}
+
+#[post("/user/2fa/disable", data = "<req_body>")]
+async fn disable_two_factor(
+    state: &State<AppState>,
+    user: AuthUser,
+    req_body: Json<DisableTwoFactorRequest>,
+    client: ClientInfo,
+    audit: Auditor<'_>,
+) -> Result<(), Error> {
+    audit.log(user.uid, AuditAction::DisableTwoFactor, "", "");
+
+    if user.uid <= 0 {
+        return Err(Error::Forbidden);
+    }
+
+    let mut conn = state.get_pool_conn().await?;
+    let user = User::find_user_by_id(user.uid, &mut conn)
+        .await?
+        .ok_or(404)?;
+    let attempt_keys = vec![
+        LoginAttempt::ip_key(&client.ip),
+        LoginAttempt::user_key(&user.username),
+    ];
+    start_attempt(&attempt_keys, &mut conn).await?;
+
+    let user = User::login(&user.username, &req_body.password, &mut conn)
+        .await
+        .map_err(|_| Error::Unauthorized)?;
+    LoginAttempt::take_back(&attempt_keys, &mut conn).await?;
+
+    if require_two_factor(state, &user)? {
+        return Err(Error::Forbidden);
+    }
+
+    let mut tx = conn.begin().await?;
+    User::update_totp_secret_query(user.user_id, "", &mut tx).await?;
+    RecoveryCode::delete_user_codes_query(user.user_id, &mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+fn require_two_factor(state: &State<AppState>, user: &User) -> AnyResult<bool> {
+    Ok(state.get_site()?.require_admin_2fa > 0 && user.permission == 9)
+}
+
+fn get_two_factor_step(state: &State<AppState>, user: &User) -> AnyResult<Option<TwoFactorStep>> {
+    if user.is_totp_enabled() {
+        Ok(Some(TwoFactorStep::Verify))
+    } else if require_two_factor(state, user)? {
+        Ok(Some(TwoFactorStep::Setup))
+    } else {
+        Ok(None)
+    }
+}
+
+// Accept either a TOTP code or an unused recovery code.
+async fn verify_two_factor_code(
+    user: &User,
+    code: &str,
+    secret: &str,
+    conn: &mut PoolConnection<Sqlite>,
+) -> AnyResult<bool> {
+    let timestamp = util::get_utc_seconds();
+    if let Some(step) = totp::verify(&user.totp_secret, code, timestamp, user.totp_last_step)? {
+        let mut tx = conn.begin().await?;
+        User::update_totp_last_step_query(user.user_id, step, &mut tx).await?;
+        tx.commit().await?;
+
+        return Ok(true);
+    }
+
+    if let Some(recovery) = RecoveryCode::find_unused(user.user_id, code, secret, conn).await? {
+        let mut tx = conn.begin().await?;
+        RecoveryCode::mark_used_query(recovery.code_id, &mut tx).await?;
+        tx.commit().await?;
+
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+// The attempt counts as a failure until it is taken back.
+async fn start_attempt(keys: &[String], conn: &mut PoolConnection<Sqlite>) -> Result<(), Error> {
+    if !LoginAttempt::start(keys, conn).await? {
+        return Err(Error::TooManyRequests);
+    }
+
+    Ok(())
+}
+
+async fn clear_attempts(key: &str, conn: &mut PoolConnection<Sqlite>) -> AnyResult<()> {
+    let mut tx = conn.begin().await?;
+    LoginAttempt::delete_query(key, &mut tx).await?;
     tx.commit().await?;
 
     Ok(())
 }
 
+async fn create_session(
+    user: &User,
+    client: &ClientInfo,
+    conn: &mut PoolConnection<Sqlite>,
+) -> AnyResult<Session> {
+    let session = Session::new(user.user_id, client);
+    let mut tx = conn.begin().await?;
+    Session::delete_expired_query(&mut tx).await?;
+    session.insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(session)
+}
+
+// Permission 0 is reserved for guest users.
+fn valid_permission(permission: i8) -> bool {
+    (1..=9).contains(&permission)
+}
+
 fn set_access_token(user: &User, secret: &str, jar: &CookieJar<'_>) -> AnyResult<AccessToken> {
     let access_token = user.generate_access_token();
     let access_token_str = access_token.encode(&secret)?;
@@ -208,8 +993,13 @@ fn set_access_token(user: &User, secret: &str, jar: &CookieJar<'_>) -> AnyResult
     Ok(access_token)
 }
 
-fn set_refresh_token(user: &User, secret: &str, jar: &CookieJar<'_>) -> AnyResult<()> {
-    let refresh_token_str = user.generate_refresh_token().encode(&secret)?;
+fn set_refresh_token(
+    user: &User,
+    session_id: &str,
+    secret: &str,
+    jar: &CookieJar<'_>,
+) -> AnyResult<()> {
+    let refresh_token_str = user.generate_refresh_token(session_id).encode(secret)?;
     let cookie = Cookie::build(REFRESH_TOKEN, refresh_token_str)
         .path("/api/user")
         .http_only(true)
@@ -221,6 +1011,28 @@ fn set_refresh_token(user: &User, secret: &str, jar: &CookieJar<'_>) -> AnyResul
     Ok(())
 }
 
+fn set_two_factor_token(user: &User, secret: &str, jar: &CookieJar<'_>) -> AnyResult<()> {
+    let token_str = TwoFactorToken::new(user.user_id).encode(secret)?;
+    let cookie = Cookie::build(TWO_FACTOR_TOKEN, token_str)
+        .path("/api")
+        .http_only(true)
+        .max_age(time::Duration::minutes(TWO_FACTOR_TOKEN_MINS))
+        .finish();
+
+    jar.add(cookie);
+
+    Ok(())
+}
+
+fn remove_two_factor_token(jar: &CookieJar<'_>) {
+    let cookie = Cookie::build(TWO_FACTOR_TOKEN, "")
+        .path("/api")
+        .http_only(true)
+        .finish();
+
+    jar.remove(cookie);
+}
+
 fn remove_tokens(jar: &CookieJar<'_>) {
     jar.remove(Cookie::named(ACCESS_TOKEN));
 
@@ -0,0 +1,194 @@
+use super::request::AclRequest;
+use crate::{
+    args,
+    util::db::{self, Query},
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum AclSubject {
+    User,
+    Group,
+}
+
+impl AclSubject {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclSubject::User => "user",
+            AclSubject::Group => "group",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Delete,
+    Share,
+}
+
+// An access control entry on a path, inherited by all the files under it.
+// The path is relative to the storage, with an empty string for the root.
+#[derive(Serialize, FromRow, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Acl {
+    pub acl_id: i64,
+    pub path: String,
+    pub subject_type: String,
+    pub subject_id: i64,
+    pub can_read: i8,
+    pub can_write: i8,
+    pub can_delete: i8,
+    pub can_share: i8,
+}
+
+impl Acl {
+    pub fn from_req(path: &str, req: &AclRequest) -> Self {
+        Self {
+            acl_id: 0,
+            path: path.trim_matches('/').to_owned(),
+            subject_type: req.subject_type.as_str().to_owned(),
+            subject_id: req.subject_id,
+            can_read: req.read as i8,
+            can_write: req.write as i8,
+            can_delete: req.delete as i8,
+            can_share: req.share as i8,
+        }
+    }
+
+    pub fn allows(&self, access: Access) -> bool {
+        let flag = match access {
+            Access::Read => self.can_read,
+            Access::Write => self.can_write,
+            Access::Delete => self.can_delete,
+            Access::Share => self.can_share,
+        };
+
+        flag > 0
+    }
+
+    // Only one entry is kept for the same subject on the same path.
+    pub async fn upsert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "insert into ACL (path, subject_type, subject_id, can_read, can_write, can_delete, can_share) values (?1, ?2, ?3, ?4, ?5, ?6, ?7) on conflict(path, subject_type, subject_id) do update set can_read = ?4, can_write = ?5, can_delete = ?6, can_share = ?7";
+        let query = Query::new(
+            sql,
+            args![
+                &self.path,
+                &self.subject_type,
+                self.subject_id,
+                self.can_read,
+                self.can_write,
+                self.can_delete,
+                self.can_share
+            ],
+        );
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(acl_id: i64, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from ACL where acl_id = ?1";
+        let query = Query::new(sql, args![acl_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from ACL";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_subject_query(
+        subject: AclSubject,
+        subject_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from ACL where subject_type = ?1 and subject_id = ?2";
+        let query = Query::new(sql, args![subject.as_str(), subject_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    // Same as `Hidden::update_all_sub_path_query()`.
+    pub async fn update_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        current_path: &str,
+        new_path: &str,
+    ) -> AnyResult<()> {
+        let sql = "update ACL set path = ?1 where path = ?2";
+        let query = Query::new(sql, args![new_path, current_path]);
+
+        db::execute(query, tx).await?;
+
+        let sql =
+            "update ACL set path = replace(substr(path, ?1, ?2), ?3, ?4) || substr(path, ?5) where path like ?6";
+        let query = Query::new(
+            sql,
+            args![
+                1,
+                current_path.len() + 2,
+                format!("{}/", current_path),
+                format!("{}/", new_path),
+                current_path.len() + 3,
+                format!("{}/%", current_path)
+            ],
+        );
+
+        db::execute(query, tx).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        path: &str,
+    ) -> AnyResult<()> {
+        let sql = "delete from ACL where path = ?1";
+        let query = Query::new(sql, args![path]);
+        db::execute(query, tx).await?;
+
+        let sql = "delete from ACL where path like ?1";
+        let query = Query::new(sql, args![format!("{}/%", path)]);
+        db::execute(query, tx).await?;
+
+        Ok(())
+    }
+
+    pub async fn find_all(conn: &mut PoolConnection<Sqlite>) -> AnyResult<Vec<Self>> {
+        let sql = "select * from ACL order by path, subject_type, subject_id";
+        let query = Query::new(sql, vec![]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn find_by_path(
+        path: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from ACL where path = ?1 order by subject_type, subject_id";
+        let query = Query::new(sql, args![path.trim_matches('/')]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn find_by_subject(
+        subject: AclSubject,
+        subject_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from ACL where subject_type = ?1 and subject_id = ?2";
+        let query = Query::new(sql, args![subject.as_str(), subject_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
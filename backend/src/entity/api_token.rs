@@ -0,0 +1,157 @@
+use crate::{
+    args,
+    util::{
+        self,
+        constants::API_TOKEN_PREFIX,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TokenScope {
+    Read,
+    Write,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+}
+
+#[derive(FromRow, Debug)]
+pub struct ApiToken {
+    pub token_id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub token_hash: String,
+    pub scope: String,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expire_at: i64,
+}
+
+// The owner info is needed by request guards to build the auth user.
+#[derive(FromRow, Debug)]
+pub struct ApiTokenOwner {
+    pub token_id: i64,
+    pub user_id: i64,
+    pub scope: String,
+    pub expire_at: i64,
+    pub permission: i8,
+    pub disabled: i8,
+}
+
+impl ApiToken {
+    // Return the record and the plain token, which is only shown to the user once.
+    pub fn new(
+        user_id: i64,
+        name: &str,
+        scope: TokenScope,
+        expire_at: i64,
+        secret: &str,
+    ) -> (Self, String) {
+        let plain = format!("{}{}", API_TOKEN_PREFIX, util::generate_secret_key(40));
+        let token = Self {
+            token_id: 0,
+            user_id,
+            name: name.to_owned(),
+            token_hash: util::sha256(&plain, secret),
+            scope: scope.as_str().to_owned(),
+            created_at: util::get_utc_seconds(),
+            last_used_at: 0,
+            expire_at,
+        };
+
+        (token, plain)
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into API_TOKEN (user_id, name, token_hash, scope, created_at, expire_at) values (?1, ?2, ?3, ?4, ?5, ?6)";
+        let query = Query::new(
+            sql,
+            args![
+                self.user_id,
+                &self.name,
+                &self.token_hash,
+                &self.scope,
+                self.created_at,
+                self.expire_at
+            ],
+        );
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    pub async fn delete_query(
+        token_id: i64,
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from API_TOKEN where token_id = ?1 and user_id = ?2";
+        let query = Query::new(sql, args![token_id, user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_user_tokens_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from API_TOKEN where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_last_used_query(
+        token_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update API_TOKEN set last_used_at = ?1 where token_id = ?2";
+        let query = Query::new(sql, args![util::get_utc_seconds(), token_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_user_tokens(
+        user_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from API_TOKEN where user_id = ?1 order by token_id";
+        let query = Query::new(sql, args![user_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    // Find the valid token owner by the plain token sent in the request header.
+    pub async fn find_owner(
+        plain: &str,
+        secret: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<ApiTokenOwner>> {
+        let sql = "select t.token_id, t.user_id, t.scope, t.expire_at, u.permission, u.disabled from API_TOKEN t join USER u on t.user_id = u.user_id where t.token_hash = ?1";
+        let query = Query::new(sql, args![util::sha256(plain, secret)]);
+        let owner: Option<ApiTokenOwner> = db::fetch_single(query, conn).await?;
+
+        Ok(owner.filter(|o| {
+            o.disabled == 0 && (o.expire_at == 0 || o.expire_at > util::get_utc_seconds())
+        }))
+    }
+}
+
+impl ApiTokenOwner {
+    pub fn is_writable(&self) -> bool {
+        self.scope == TokenScope::Write.as_str()
+    }
+}
@@ -0,0 +1,307 @@
+use super::request::AuditFilter;
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::Serialize;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+    Login,
+    LoginTwoFactor,
+    GuestLogin,
+    Signout,
+    ChangePassword,
+    ForgotPassword,
+    ResetPassword,
+    CreateUser,
+    UpdatePermission,
+    UpdateUserStatus,
+    UpdateHomeDir,
+    UpdateUserQuota,
+    DeleteUser,
+    CreateApiToken,
+    RevokeApiToken,
+    RevokeSession,
+    RevokeAllSessions,
+    SetupTwoFactor,
+    EnableTwoFactor,
+    DisableTwoFactor,
+    CreateDir,
+    Rename,
+    Delete,
+    SetVisibility,
+    Share,
+    RevokeShare,
+    Copy,
+    Move,
+    StartUpload,
+    FinishUpload,
+    CancelUpload,
+    PutFile,
+    Fetch,
+    CancelFetch,
+    CancelJob,
+    Setup,
+    UpdateSite,
+    ClearLockout,
+    UpdateAcl,
+    DeleteAcl,
+    CreateGroup,
+    UpdateGroup,
+    DeleteGroup,
+    AddGroupMember,
+    RemoveGroupMember,
+    UpdateDirQuota,
+    DeleteDirQuota,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "user.login",
+            AuditAction::LoginTwoFactor => "user.login_2fa",
+            AuditAction::GuestLogin => "user.guest_login",
+            AuditAction::Signout => "user.signout",
+            AuditAction::ChangePassword => "user.change_password",
+            AuditAction::ForgotPassword => "user.forgot_password",
+            AuditAction::ResetPassword => "user.reset_password",
+            AuditAction::CreateUser => "user.create",
+            AuditAction::UpdatePermission => "user.update_permission",
+            AuditAction::UpdateUserStatus => "user.update_status",
+            AuditAction::UpdateHomeDir => "user.update_home",
+            AuditAction::UpdateUserQuota => "user.update_quota",
+            AuditAction::DeleteUser => "user.delete",
+            AuditAction::CreateApiToken => "token.create",
+            AuditAction::RevokeApiToken => "token.revoke",
+            AuditAction::RevokeSession => "session.revoke",
+            AuditAction::RevokeAllSessions => "session.revoke_all",
+            AuditAction::SetupTwoFactor => "2fa.setup",
+            AuditAction::EnableTwoFactor => "2fa.enable",
+            AuditAction::DisableTwoFactor => "2fa.disable",
+            AuditAction::CreateDir => "file.create_dir",
+            AuditAction::Rename => "file.rename",
+            AuditAction::Delete => "file.delete",
+            AuditAction::SetVisibility => "file.visibility",
+            AuditAction::Share => "file.share",
+            AuditAction::RevokeShare => "share.revoke",
+            AuditAction::Copy => "file.copy",
+            AuditAction::Move => "file.move",
+            AuditAction::StartUpload => "upload.start",
+            AuditAction::FinishUpload => "upload.finish",
+            AuditAction::CancelUpload => "upload.cancel",
+            AuditAction::PutFile => "upload.put",
+            AuditAction::Fetch => "fetch.start",
+            AuditAction::CancelFetch => "fetch.cancel",
+            AuditAction::CancelJob => "job.cancel",
+            AuditAction::Setup => "sys.setup",
+            AuditAction::UpdateSite => "sys.update_site",
+            AuditAction::ClearLockout => "sys.clear_lockout",
+            AuditAction::UpdateAcl => "acl.update",
+            AuditAction::DeleteAcl => "acl.delete",
+            AuditAction::CreateGroup => "group.create",
+            AuditAction::UpdateGroup => "group.update",
+            AuditAction::DeleteGroup => "group.delete",
+            AuditAction::AddGroupMember => "group.add_member",
+            AuditAction::RemoveGroupMember => "group.remove_member",
+            AuditAction::UpdateDirQuota => "quota.update_dir",
+            AuditAction::DeleteDirQuota => "quota.delete_dir",
+        }
+    }
+}
+
+// The result is decided by the response status, so failed operations are recorded too.
+#[derive(Serialize, FromRow, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Audit {
+    pub audit_id: i64,
+    pub user_id: i64,
+    pub ip: String,
+    pub action: String,
+    pub path: String,
+    pub target: String,
+    pub result: String,
+    pub status: i64,
+    pub created_at: i64,
+}
+
+#[derive(FromRow, Debug)]
+struct AuditCount {
+    total: i64,
+}
+
+impl Audit {
+    pub fn new(
+        user_id: i64,
+        ip: &str,
+        action: AuditAction,
+        path: &str,
+        target: &str,
+        status: u16,
+    ) -> Self {
+        let result = if status < 400 { "success" } else { "failure" };
+
+        Self {
+            audit_id: 0,
+            user_id,
+            ip: ip.to_owned(),
+            action: action.as_str().to_owned(),
+            path: path.to_owned(),
+            target: target.to_owned(),
+            result: result.to_owned(),
+            status: status as i64,
+            created_at: util::get_utc_seconds(),
+        }
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into AUDIT (user_id, ip, action, path, target, result, status, created_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+        let query = Query::new(
+            sql,
+            args![
+                self.user_id,
+                &self.ip,
+                &self.action,
+                &self.path,
+                &self.target,
+                &self.result,
+                self.status,
+                self.created_at
+            ],
+        );
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    // Newest first. A limit of 0 returns all the matched records.
+    pub async fn find(
+        filter: &AuditFilter,
+        limit: i64,
+        offset: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let (where_clause, args) = filter_clause(filter);
+        let mut sql = format!(
+            "select * from AUDIT{} order by created_at desc, audit_id desc",
+            where_clause
+        );
+        if limit > 0 {
+            sql.push_str(&format!(" limit {} offset {}", limit, offset.max(0)));
+        }
+
+        let query = Query::new(&sql, args);
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn count(filter: &AuditFilter, conn: &mut PoolConnection<Sqlite>) -> AnyResult<i64> {
+        let (where_clause, args) = filter_clause(filter);
+        let sql = format!("select count(*) as total from AUDIT{}", where_clause);
+        let query = Query::new(&sql, args);
+        let count: Option<AuditCount> = db::fetch_single(query, conn).await?;
+
+        Ok(count.map(|c| c.total).unwrap_or(0))
+    }
+
+    pub fn csv_header() -> &'static str {
+        "audit_id,user_id,ip,action,path,target,result,status,created_at"
+    }
+
+    pub fn to_csv_row(&self) -> String {
+        let fields = [
+            self.audit_id.to_string(),
+            self.user_id.to_string(),
+            csv_field(&self.ip),
+            csv_field(&self.action),
+            csv_field(&self.path),
+            csv_field(&self.target),
+            csv_field(&self.result),
+            self.status.to_string(),
+            self.created_at.to_string(),
+        ];
+
+        fields.join(",")
+    }
+}
+
+fn filter_clause(filter: &AuditFilter) -> (String, Vec<String>) {
+    let mut conditions = vec![];
+    let mut args = vec![];
+
+    if let Some(user_id) = filter.user_id {
+        args.push(user_id.to_string());
+        conditions.push(format!("user_id = ?{}", args.len()));
+    }
+
+    if let Some(action) = &filter.action {
+        args.push(action.to_owned());
+        conditions.push(format!("action = ?{}", args.len()));
+    }
+
+    if let Some(path) = &filter.path {
+        args.push(format!("%{}%", path));
+        conditions.push(format!("(path like ?{0} or target like ?{0})", args.len()));
+    }
+
+    if let Some(result) = &filter.result {
+        args.push(result.to_owned());
+        conditions.push(format!("result = ?{}", args.len()));
+    }
+
+    if let Some(from) = filter.from {
+        args.push(from.to_string());
+        conditions.push(format!("created_at >= ?{}", args.len()));
+    }
+
+    if let Some(to) = filter.to {
+        args.push(to.to_string());
+        conditions.push(format!("created_at <= ?{}", args.len()));
+    }
+
+    if conditions.is_empty() {
+        (String::new(), args)
+    } else {
+        (format!(" where {}", conditions.join(" and ")), args)
+    }
+}
+
+// Quote the field if it contains any separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("docs/a.txt"), "docs/a.txt");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_filter_clause() {
+        let filter = AuditFilter {
+            user_id: Some(2),
+            path: Some(String::from("docs")),
+            ..Default::default()
+        };
+        let (clause, args) = filter_clause(&filter);
+
+        assert_eq!(
+            clause,
+            " where user_id = ?1 and (path like ?2 or target like ?2)"
+        );
+        assert_eq!(args, vec!["2", "%docs%"]);
+    }
+}
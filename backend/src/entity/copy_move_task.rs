@@ -1,9 +1,10 @@
-use fs_extra::dir;
-use fs_extra::{copy_items_with_progress, TransitProcess};
+use crate::util::{self, constants::COPY_BUFFER_SIZE};
+use anyhow::Result as AnyResult;
 use rocket::serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-
-use crate::COPY_MOVE_TASK;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 #[derive(Deserialize, Clone)]
 #[serde(crate = "rocket::serde")]
@@ -21,6 +22,21 @@ pub enum CopyMoveTaskStatus {
     InProgress,
     Success,
     Failed,
+    Paused,
+    Cancelled,
+}
+
+impl CopyMoveTaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CopyMoveTaskStatus::Pending => "Pending",
+            CopyMoveTaskStatus::InProgress => "InProgress",
+            CopyMoveTaskStatus::Success => "Success",
+            CopyMoveTaskStatus::Failed => "Failed",
+            CopyMoveTaskStatus::Paused => "Paused",
+            CopyMoveTaskStatus::Cancelled => "Cancelled",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -34,6 +50,8 @@ pub struct CopyMoveTask {
     pub progress: f64,
     pub is_copy: bool,
     pub overwrite: bool,
+    pub error: String,
+    pub created_at: i64,
 }
 
 impl CopyMoveTask {
@@ -54,70 +72,371 @@ impl CopyMoveTask {
             is_copy,
             status: CopyMoveTaskStatus::Pending,
             overwrite,
+            error: String::new(),
+            created_at: util::get_utc_seconds(),
         }
     }
 
-    pub fn run(&self) {
-        let task = self.clone();
-        std::thread::spawn(move || {
-            let mut options = dir::CopyOptions::new();
-            if task.overwrite {
-                options.overwrite = true;
-                options.skip_exist = false;
-            } else {
-                options.skip_exist = true;
+    // Blocks until done, so it is run in a blocking thread by the job manager. The callback
+    // is given the fraction of bytes copied, and returns false to cancel the task.
+    // A cancelled or failed task removes what it has created in the target, puts back the
+    // files it has overwritten and never touches the source.
+    // A move that doesn't overwrite fails on any existing file, which it couldn't remove
+    // from the source without losing it.
+    pub fn run<F: FnMut(f64) -> bool>(&self, mut on_progress: F) -> AnyResult<()> {
+        let dest = self.dest()?;
+
+        // Within a device a move is an atomic rename, unless it has to merge into the dest.
+        if !self.is_copy && !dest.exists() && same_device(&self.source, &self.target) {
+            match fs::rename(&self.source, &dest) {
+                Ok(_) => {
+                    on_progress(1.0);
+                    return Ok(());
+                }
+                Err(e) => eprintln!("Error renaming {:?}, copying instead: {}", self.source, e),
+            }
+        }
+
+        if !self.is_copy && !self.overwrite {
+            let existing = self.count_existing()?;
+            if existing > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} files already exist in the target",
+                    existing
+                ));
             }
+        }
+
+        let mut transfer = Transfer {
+            uuid: &self.uuid,
+            overwrite: self.overwrite,
+            total_bytes: fs_extra::dir::get_size(&self.source)?,
+            copied_bytes: 0,
+            created: vec![],
+            replaced: vec![],
+            on_progress,
+        };
+
+        match transfer.copy(&self.source, &dest) {
+            Ok(true) => transfer.commit(),
+            Ok(false) => {
+                transfer.rollback();
+                return Err(anyhow::anyhow!(
+                    "Cancelled, {} copied items are removed and {} overwritten files restored",
+                    transfer.created.len(),
+                    transfer.replaced.len()
+                ));
+            }
+            Err(e) => {
+                transfer.rollback();
+                return Err(e);
+            }
+        }
 
-            let handle = |info: TransitProcess| {
-                task.update_progress(
-                    info.copied_bytes as f64 / info.total_bytes as f64,
-                    CopyMoveTaskStatus::InProgress,
-                );
-                dir::TransitProcessResult::ContinueOrAbort
+        // Only reached once every file is copied.
+        if !self.is_copy {
+            fs_extra::remove_items(&[&self.source])?;
+        }
+
+        Ok(())
+    }
+
+    // A dir can't go into itself, and a move into the dir it is in would delete the source
+    // after skipping it as an existing file.
+    pub fn dest(&self) -> AnyResult<PathBuf> {
+        let name = self
+            .source
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Invalid source: {:?}", self.source))?;
+        let dest = self.target.join(name);
+        if self.target.starts_with(&self.source) || dest == self.source {
+            return Err(anyhow::anyhow!(
+                "Cannot copy or move {:?} into {:?}",
+                self.source,
+                self.target
+            ));
+        }
+
+        Ok(dest)
+    }
+
+    // The files of the source that are already in the target, walking the whole source.
+    pub fn count_existing(&self) -> AnyResult<usize> {
+        let dest = self.dest()?;
+        let mut count = 0;
+        for entry in WalkDir::new(&self.source).follow_links(false) {
+            let entry = entry?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+
+            let relative_path = entry.path().strip_prefix(&self.source)?;
+            let existing = if relative_path.as_os_str().is_empty() {
+                dest.clone()
+            } else {
+                dest.join(relative_path)
             };
+            if existing.exists() {
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+// False when unknown, which only costs a copy.
+#[cfg(unix)]
+fn same_device(source: &Path, target_dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (source.symlink_metadata(), target_dir.metadata()) {
+        (Ok(source), Ok(target_dir)) => source.dev() == target_dir.dev(),
+        _ => false,
+    }
+}
+
+// The same drive or share, where a failed rename still falls back to a copy.
+#[cfg(not(unix))]
+fn same_device(source: &Path, target_dir: &Path) -> bool {
+    source.components().next() == target_dir.components().next()
+}
+
+// Copies files through part files renamed into place, so an interrupted copy never leaves
+// a partial file under a real name, and records what it creates to be rolled back. A file
+// it replaces is kept as a backup next to it until the whole transfer is done.
+struct Transfer<'a, F> {
+    uuid: &'a str,
+    overwrite: bool,
+    total_bytes: u64,
+    copied_bytes: u64,
+    created: Vec<PathBuf>,
+    // The replaced files and their backups.
+    replaced: Vec<(PathBuf, PathBuf)>,
+    on_progress: F,
+}
+
+impl<'a, F: FnMut(f64) -> bool> Transfer<'a, F> {
+    // Return false if cancelled.
+    fn copy(&mut self, source: &Path, dest: &Path) -> AnyResult<bool> {
+        if !source.is_dir() {
+            return self.copy_file(source, dest);
+        }
 
-            let from_paths = vec![&task.source];
-            if let Err(e) = copy_items_with_progress(&from_paths, &task.target, &options, handle) {
-                eprintln!("Error: {}", e);
-                task.update_progress(0.0, CopyMoveTaskStatus::Failed);
+        if !dest.exists() {
+            fs::create_dir(dest)?;
+            self.created.push(dest.to_path_buf());
+        }
+
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            if !self.copy(&entry.path(), &dest.join(entry.file_name()))? {
+                return Ok(false);
             }
+        }
 
-            if !task.is_copy {
-                if let Err(e) = fs_extra::remove_items(&vec![&task.source]) {
-                    eprintln!("Error: {}", e);
-                    task.update_progress(0.0, CopyMoveTaskStatus::Failed);
-                    return;
+        Ok(true)
+    }
+
+    // An existing file is skipped unless overwriting, which only a copy gets to.
+    fn copy_file(&mut self, source: &Path, dest: &Path) -> AnyResult<bool> {
+        if !self.overwrite && dest.exists() {
+            self.copied_bytes += source.metadata()?.len();
+            return Ok(self.report());
+        }
+
+        let name = dest.file_name().unwrap_or_default().to_string_lossy();
+        let part_file = dest.with_file_name(format!(".{}.{}.part", name, self.uuid));
+        match self.write_part(source, &part_file) {
+            Ok(true) => {}
+            result => {
+                if part_file.exists() {
+                    fs::remove_file(&part_file)?;
                 }
+                return result;
             }
+        }
 
-            task.update_progress(1.0, CopyMoveTaskStatus::Success);
-        });
+        // A dir in the way is left for the rename to fail on.
+        if dest.is_file() {
+            let backup = dest.with_file_name(format!(".{}.{}.orig", name, self.uuid));
+            fs::rename(dest, &backup)?;
+            if let Err(e) = fs::rename(&part_file, dest) {
+                fs::rename(&backup, dest)?;
+                fs::remove_file(&part_file)?;
+                return Err(e.into());
+            }
+            self.replaced.push((dest.to_path_buf(), backup));
+        } else {
+            fs::rename(&part_file, dest)?;
+            self.created.push(dest.to_path_buf());
+        }
+
+        Ok(self.report())
     }
 
-    pub fn update_progress(&self, progress: f64, status: CopyMoveTaskStatus) {
-        let mut updated_task = self.clone();
-        updated_task.progress = progress;
-        updated_task.status = status;
-        updated_task.set_static_value();
+    fn write_part(&mut self, source: &Path, part_file: &Path) -> AnyResult<bool> {
+        let mut reader = fs::File::open(source)?;
+        let mut writer = fs::File::create(part_file)?;
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+
+            writer.write_all(&buffer[..n])?;
+            self.copied_bytes += n as u64;
+            if !self.report() {
+                return Ok(false);
+            }
+        }
+        writer.sync_all()?;
+        fs::set_permissions(part_file, reader.metadata()?.permissions())?;
+
+        Ok(true)
     }
 
-    pub fn set_static_value(&self) {
-        let mut copy_move_task = COPY_MOVE_TASK.lock().unwrap();
-        *copy_move_task = Some(self.clone());
+    fn report(&mut self) -> bool {
+        let progress = if self.total_bytes > 0 {
+            self.copied_bytes as f64 / self.total_bytes as f64
+        } else {
+            1.0
+        };
+
+        (self.on_progress)(progress)
     }
 
-    pub fn get_static_value() -> Option<Self> {
-        let copy_move_task = COPY_MOVE_TASK.lock().unwrap();
-        copy_move_task.clone()
+    fn commit(&self) {
+        for (_, backup) in &self.replaced {
+            if let Err(e) = fs::remove_file(backup) {
+                eprintln!("Error removing {:?}: {}", backup, e);
+            }
+        }
     }
 
-    pub fn allow_new_task() -> bool {
-        let task = Self::get_static_value();
-        if task.is_none() {
-            return true;
+    // The dirs are emptied before being removed, as they are created before their files.
+    fn rollback(&self) {
+        for (path, backup) in &self.replaced {
+            if let Err(e) = fs::rename(backup, path) {
+                eprintln!("Error restoring {:?}: {}", path, e);
+            }
         }
 
-        let task = task.as_ref().unwrap();
-        task.status == CopyMoveTaskStatus::Success || task.status == CopyMoveTaskStatus::Failed
+        for path in self.created.iter().rev() {
+            let result = if path.is_dir() {
+                fs::remove_dir(path)
+            } else {
+                fs::remove_file(path)
+            };
+
+            if let Err(e) = result {
+                eprintln!("Error removing {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_rolls_back() {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let source = root.join("src");
+        let target = root.join("dst");
+        fs::create_dir_all(source.join("d")).unwrap();
+        fs::create_dir_all(target.join("src")).unwrap();
+        fs::write(source.join("a"), vec![1; COPY_BUFFER_SIZE * 2]).unwrap();
+        fs::write(source.join("d").join("b"), b"b").unwrap();
+        fs::write(target.join("src").join("a"), b"old").unwrap();
+
+        // A move that doesn't overwrite can't skip the existing file without losing it.
+        let task = CopyMoveTask::new(source.clone(), target.clone(), 1, false, false);
+        assert_eq!(task.count_existing().unwrap(), 1);
+        assert!(task.run(|_| true).is_err());
+        assert_eq!(
+            fs::read(source.join("a")).unwrap().len(),
+            COPY_BUFFER_SIZE * 2
+        );
+        assert_eq!(fs::read(target.join("src").join("a")).unwrap(), b"old");
+        assert_eq!(fs::read_dir(target.join("src")).unwrap().count(), 1);
+
+        // Cancelled once the last file is in place, after the existing one is replaced.
+        let task = CopyMoveTask::new(source.clone(), target.clone(), 1, false, true);
+        let mut done_reports = 0;
+        let result = task.run(|progress| {
+            if progress >= 1.0 {
+                done_reports += 1;
+            }
+            done_reports < 2
+        });
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("1 overwritten files restored"));
+        assert_eq!(fs::read(source.join("d").join("b")).unwrap(), b"b");
+        assert_eq!(fs::read(target.join("src").join("a")).unwrap(), b"old");
+        assert_eq!(fs::read_dir(target.join("src")).unwrap().count(), 1);
+
+        task.run(|_| true).unwrap();
+        assert!(!source.exists());
+        assert_eq!(
+            fs::read(target.join("src").join("a")).unwrap().len(),
+            COPY_BUFFER_SIZE * 2
+        );
+        assert_eq!(
+            fs::read(target.join("src").join("d").join("b")).unwrap(),
+            b"b"
+        );
+        assert_eq!(fs::read_dir(target.join("src")).unwrap().count(), 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_move() {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let source = root.join("src");
+        let target = root.join("dst");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(source.join("a"), b"a").unwrap();
+
+        assert!(
+            CopyMoveTask::new(source.clone(), source.join("x"), 1, false, false)
+                .dest()
+                .is_err()
+        );
+        assert!(
+            CopyMoveTask::new(source.join("a"), source.clone(), 1, false, false)
+                .dest()
+                .is_err()
+        );
+
+        // A file in the way of the dir fails the copy, which must keep the source.
+        fs::write(target.join("src"), b"file").unwrap();
+        let task = CopyMoveTask::new(source.clone(), target.clone(), 1, false, false);
+        assert!(task.run(|_| true).is_err());
+        assert_eq!(fs::read(source.join("a")).unwrap(), b"a");
+
+        fs::remove_file(target.join("src")).unwrap();
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            source.join("a").metadata().unwrap().ino()
+        };
+        task.run(|_| true).unwrap();
+        assert!(!source.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(
+                target.join("src").join("a").metadata().unwrap().ino(),
+                inode
+            );
+        }
+        fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -1,6 +1,7 @@
 use rocket::http::Status;
 use rocket::request::Request;
 use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
 use std::error::Error as StdError;
 use std::fmt;
 
@@ -9,9 +10,14 @@ pub enum Error {
     BadRequest,
     Conflict,
     Forbidden,
+    InsufficientStorage,
     InternalServerError,
+    // An upload finished without the slices of the given indices.
+    MissingSlices(Vec<u64>),
     NotFound,
+    TooManyRequests,
     Unauthorized,
+    UnprocessableEntity,
 }
 
 impl fmt::Display for Error {
@@ -20,9 +26,13 @@ impl fmt::Display for Error {
             Error::BadRequest => f.write_str("BadRequest"),
             Error::Conflict => f.write_str("Conflict"),
             Error::Forbidden => f.write_str("Forbidden"),
+            Error::InsufficientStorage => f.write_str("InsufficientStorage"),
             Error::InternalServerError => f.write_str("InternalServerError"),
+            Error::MissingSlices(_) => f.write_str("MissingSlices"),
             Error::NotFound => f.write_str("NotFound"),
+            Error::TooManyRequests => f.write_str("TooManyRequests"),
             Error::Unauthorized => f.write_str("Unauthorized"),
+            Error::UnprocessableEntity => f.write_str("UnprocessableEntity"),
         }
     }
 }
@@ -33,22 +43,30 @@ impl StdError for Error {
             Error::BadRequest => "BadRequest",
             Error::Conflict => "Conflict",
             Error::Forbidden => "Forbidden",
+            Error::InsufficientStorage => "InsufficientStorage",
             Error::InternalServerError => "InternalServerError",
+            Error::MissingSlices(_) => "MissingSlices",
             Error::NotFound => "NotFound",
+            Error::TooManyRequests => "TooManyRequests",
             Error::Unauthorized => "Unauthorized",
+            Error::UnprocessableEntity => "UnprocessableEntity",
         }
     }
 }
 
 impl<'r> Responder<'r, 'static> for Error {
-    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
         match self {
             Error::BadRequest => Err(Status::BadRequest),
             Error::Conflict => Err(Status::Conflict),
             Error::Forbidden => Err(Status::Forbidden),
+            Error::InsufficientStorage => Err(Status::InsufficientStorage),
             Error::InternalServerError => Err(Status::InternalServerError),
+            Error::MissingSlices(indices) => (Status::BadRequest, Json(indices)).respond_to(req),
             Error::NotFound => Err(Status::NotFound),
+            Error::TooManyRequests => Err(Status::TooManyRequests),
             Error::Unauthorized => Err(Status::Unauthorized),
+            Error::UnprocessableEntity => Err(Status::UnprocessableEntity),
         }
     }
 }
@@ -79,6 +97,8 @@ impl From<i32> for Error {
             401 => Error::Unauthorized,
             403 => Error::Forbidden,
             404 => Error::NotFound,
+            422 => Error::UnprocessableEntity,
+            429 => Error::TooManyRequests,
             _ => Error::InternalServerError,
         }
     }
@@ -0,0 +1,67 @@
+use super::upload_task::ConflictPolicy;
+use rocket::serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub enum FetchTaskStatus {
+    Pending,
+    InProgress,
+    Success,
+    Failed,
+    Cancelled,
+}
+
+// Downloads a url into a dir in the background. The total is zero until the server tells
+// the length, and the max size is zero for no limit but the free space. The finished time
+// is zero until the task is finished.
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FetchTask {
+    pub uuid: String,
+    pub user_id: i64,
+    pub status: FetchTaskStatus,
+    pub url: String,
+    pub target: PathBuf,
+    pub filename: String,
+    pub conflict: ConflictPolicy,
+    pub max_size: u64,
+    pub downloaded: u64,
+    pub total: u64,
+    pub progress: f64,
+    pub error: String,
+    pub finished_at: i64,
+}
+
+impl FetchTask {
+    pub fn new(
+        url: &str,
+        target: PathBuf,
+        filename: &str,
+        user_id: i64,
+        conflict: ConflictPolicy,
+        max_size: u64,
+    ) -> Self {
+        FetchTask {
+            uuid: uuid::Uuid::new_v4().to_string(),
+            user_id,
+            status: FetchTaskStatus::Pending,
+            url: url.to_owned(),
+            target,
+            filename: filename.to_owned(),
+            conflict,
+            max_size,
+            downloaded: 0,
+            total: 0,
+            progress: 0.0,
+            error: String::new(),
+            finished_at: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status == FetchTaskStatus::Success
+            || self.status == FetchTaskStatus::Failed
+            || self.status == FetchTaskStatus::Cancelled
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::Result as AnyResult;
 use rocket::serde::Serialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
@@ -51,7 +51,7 @@ impl File {
     pub fn from_path(
         path: &PathBuf,
         need_dir: bool,
-        storage: &str,
+        root: &Path,
         least_permission: i8,
     ) -> AnyResult<Self> {
         let filename = match path.file_name() {
@@ -68,7 +68,7 @@ impl File {
         };
 
         let dir = if need_dir {
-            let parent_dir = path.parent().unwrap().strip_prefix(storage)?;
+            let parent_dir = path.parent().unwrap().strip_prefix(root)?;
             Some(PathBuf::from(parent_dir))
         } else {
             None
@@ -0,0 +1,155 @@
+use super::user::User;
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::Serialize;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+// The table is named `USER_GROUP` as `GROUP` is a keyword in SQL.
+#[derive(Serialize, FromRow, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct Group {
+    pub group_id: i64,
+    pub name: String,
+    pub created_at: i64,
+}
+
+impl Group {
+    pub fn new(name: &str) -> Self {
+        Self {
+            group_id: 0,
+            name: name.to_owned(),
+            created_at: util::get_utc_seconds(),
+        }
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into USER_GROUP (name, created_at) values (?1, ?2)";
+        let query = Query::new(sql, args![&self.name, self.created_at]);
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    pub async fn update_name_query(
+        group_id: i64,
+        name: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER_GROUP set name = ?1 where group_id = ?2";
+        let query = Query::new(sql, args![name, group_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(group_id: i64, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from USER_GROUP where group_id = ?1";
+        let query = Query::new(sql, args![group_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(conn: &mut PoolConnection<Sqlite>) -> AnyResult<Vec<Self>> {
+        let sql = "select * from USER_GROUP order by name";
+        let query = Query::new(sql, vec![]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn find_by_id(
+        group_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql = "select * from USER_GROUP where group_id = ?1";
+        let query = Query::new(sql, args![group_id]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    pub async fn find_by_name(
+        name: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql = "select * from USER_GROUP where name = ?1";
+        let query = Query::new(sql, args![name]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    pub async fn find_user_groups(
+        user_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select USER_GROUP.* from USER_GROUP join GROUP_MEMBER on USER_GROUP.group_id = GROUP_MEMBER.group_id where GROUP_MEMBER.user_id = ?1 order by USER_GROUP.name";
+        let query = Query::new(sql, args![user_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+pub struct GroupMember;
+
+impl GroupMember {
+    // Adding an existing member is a no-op.
+    pub async fn insert_query(
+        group_id: i64,
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "insert or ignore into GROUP_MEMBER (group_id, user_id) values (?1, ?2)";
+        let query = Query::new(sql, args![group_id, user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(
+        group_id: i64,
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from GROUP_MEMBER where group_id = ?1 and user_id = ?2";
+        let query = Query::new(sql, args![group_id, user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_group_members_query(
+        group_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from GROUP_MEMBER where group_id = ?1";
+        let query = Query::new(sql, args![group_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_user_memberships_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from GROUP_MEMBER where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_group_members(
+        group_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<User>> {
+        let sql = "select USER.* from USER join GROUP_MEMBER on USER.user_id = GROUP_MEMBER.user_id where GROUP_MEMBER.group_id = ?1 order by USER.username";
+        let query = Query::new(sql, args![group_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
@@ -12,20 +12,24 @@ pub struct Hidden {
     pub hidden_id: i64,
     pub path: String,
     pub least_permission: i8,
+    // Members of the group can see the path regardless of their permission.
+    pub group_id: i64,
 }
 
 impl Hidden {
-    pub fn new(path: &str, least_permission: i8) -> Self {
+    pub fn new(path: &str, least_permission: i8, group_id: i64) -> Self {
         Self {
             hidden_id: 0,
             path: String::from(path),
             least_permission,
+            group_id,
         }
     }
 
     pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
-        let sql = "insert into HIDDEN (path, least_permission) values (?1, ?2)";
-        let query = Query::new(sql, args![&self.path, self.least_permission]);
+        let sql =
+            "insert or replace into HIDDEN (path, least_permission, group_id) values (?1, ?2, ?3)";
+        let query = Query::new(sql, args![&self.path, self.least_permission, self.group_id]);
 
         let uid = db::execute(query, tx).await?;
         Ok(uid)
@@ -39,6 +43,17 @@ impl Hidden {
         Ok(())
     }
 
+    pub async fn clear_group_query(
+        group_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update HIDDEN set group_id = 0 where group_id = ?1";
+        let query = Query::new(sql, args![group_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
     pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
         let sql = "delete from HIDDEN";
         let query = Query {
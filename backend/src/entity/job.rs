@@ -0,0 +1,107 @@
+use super::copy_move_task::CopyMoveTask;
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+use std::path::Path;
+
+// A finished copy or move, kept so users can still see what became of their tasks.
+// The paths are relative to the storage.
+#[derive(FromRow, Debug, Clone)]
+pub struct Job {
+    pub uuid: String,
+    pub user_id: i64,
+    pub kind: String,
+    pub source: String,
+    pub target: String,
+    pub status: String,
+    pub error: String,
+    pub created_at: i64,
+    pub finished_at: i64,
+}
+
+impl Job {
+    pub fn new(task: &CopyMoveTask, storage: &Path) -> Self {
+        let kind = if task.is_copy { "copy" } else { "move" };
+
+        Self {
+            uuid: task.uuid.to_owned(),
+            user_id: task.user_id,
+            kind: kind.to_owned(),
+            source: storage_relative(&task.source, storage),
+            target: storage_relative(&task.target, storage),
+            status: task.status.as_str().to_owned(),
+            error: task.error.to_owned(),
+            created_at: task.created_at,
+            finished_at: util::get_utc_seconds(),
+        }
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into JOB (uuid, user_id, kind, source, target, status, error, created_at, finished_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+        let query = Query::new(
+            sql,
+            args![
+                &self.uuid,
+                self.user_id,
+                &self.kind,
+                &self.source,
+                &self.target,
+                &self.status,
+                &self.error,
+                self.created_at,
+                self.finished_at
+            ],
+        );
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    pub async fn delete_user_jobs_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from JOB where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_uuid(
+        uuid: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql = "select * from JOB where uuid = ?1";
+        let query = Query::new(sql, args![uuid]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    // The latest first.
+    pub async fn find_user_jobs(
+        user_id: i64,
+        limit: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = format!(
+            "select * from JOB where user_id = ?1 order by finished_at desc limit {}",
+            limit
+        );
+        let query = Query::new(&sql, args![user_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+pub fn storage_relative(path: &Path, storage: &Path) -> String {
+    path.strip_prefix(storage)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
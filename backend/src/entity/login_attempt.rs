@@ -0,0 +1,153 @@
+use crate::{
+    args,
+    util::{
+        self,
+        constants::{
+            LOGIN_ATTEMPT_WINDOW_SECS, LOGIN_FREE_ATTEMPTS, LOGIN_LOCK_BASE_SECS,
+            LOGIN_LOCK_MAX_SECS,
+        },
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::Serialize;
+use sqlx::{pool::PoolConnection, Connection, FromRow, Sqlite, Transaction};
+
+// Failed attempts are counted per client IP, per username and per password protected
+// share, with keys like `ip:127.0.0.1`, `user:admin` or `share:AbCdE12345`.
+#[derive(Serialize, FromRow, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct LoginAttempt {
+    pub attempt_key: String,
+    pub failures: i64,
+    pub locked_until: i64,
+    pub updated_at: i64,
+}
+
+impl LoginAttempt {
+    pub fn ip_key(ip: &str) -> String {
+        format!("ip:{}", ip)
+    }
+
+    pub fn user_key(username: &str) -> String {
+        format!("user:{}", username.to_lowercase())
+    }
+
+    pub fn share_key(slug: &str) -> String {
+        format!("share:{}", slug)
+    }
+
+    // Count the attempt as a failure before checking it, so concurrent attempts can't all
+    // get past the lockout. Each key is checked and counted in a single statement. Return
+    // false if any key is locked, in which case the attempt is not counted.
+    pub async fn start(keys: &[String], conn: &mut PoolConnection<Sqlite>) -> AnyResult<bool> {
+        let current_timestamp = util::get_utc_seconds();
+        let mut counted = vec![];
+
+        for key in keys.iter() {
+            let sql = "insert into LOGIN_ATTEMPT (attempt_key, failures, locked_until, updated_at) values (?1, 1, 0, ?2) on conflict (attempt_key) do update set failures = case when ?2 - updated_at < ?3 then failures + 1 else 1 end, updated_at = ?2 where locked_until <= ?2 returning *";
+            let query = Query::new(
+                sql,
+                args![key, current_timestamp, LOGIN_ATTEMPT_WINDOW_SECS],
+            );
+
+            let attempt: Self = match db::fetch_single(query, conn).await? {
+                Some(attempt) => attempt,
+                None => {
+                    Self::take_back(&counted, conn).await?;
+                    return Ok(false);
+                }
+            };
+            counted.push(key.to_owned());
+
+            let lock = lock_seconds(attempt.failures);
+            if lock > 0 {
+                let mut tx = conn.begin().await?;
+                Self::lock_query(key, current_timestamp + lock, &mut tx).await?;
+                tx.commit().await?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Take back the attempt counted by `start` once it turns out right, which unlocks the
+    // keys left with fewer failures than the free attempts.
+    pub async fn take_back(keys: &[String], conn: &mut PoolConnection<Sqlite>) -> AnyResult<()> {
+        let sql = "update LOGIN_ATTEMPT set failures = max(failures - 1, 0), locked_until = case when failures - 1 < ?2 then min(locked_until, ?3) else locked_until end where attempt_key = ?1";
+
+        let mut tx = conn.begin().await?;
+        for key in keys.iter() {
+            let query = Query::new(
+                sql,
+                args![key, LOGIN_FREE_ATTEMPTS, util::get_utc_seconds()],
+            );
+            db::execute(query, &mut tx).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn lock_query(
+        key: &str,
+        locked_until: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql =
+            "update LOGIN_ATTEMPT set locked_until = max(locked_until, ?2) where attempt_key = ?1";
+        let query = Query::new(sql, args![key, locked_until]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(key: &str, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from LOGIN_ATTEMPT where attempt_key = ?1";
+        let query = Query::new(sql, args![key]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from LOGIN_ATTEMPT";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_all_locked(conn: &mut PoolConnection<Sqlite>) -> AnyResult<Vec<Self>> {
+        let sql = "select * from LOGIN_ATTEMPT where locked_until > ?1 order by locked_until desc";
+        let query = Query::new(sql, args![util::get_utc_seconds()]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+// Lock the key after the free attempts, doubling the time for every further failure.
+fn lock_seconds(failures: i64) -> i64 {
+    if failures < LOGIN_FREE_ATTEMPTS {
+        return 0;
+    }
+
+    let exponent = (failures - LOGIN_FREE_ATTEMPTS).min(16) as u32;
+    (LOGIN_LOCK_BASE_SECS * 2i64.pow(exponent)).min(LOGIN_LOCK_MAX_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_seconds() {
+        assert_eq!(lock_seconds(1), 0);
+        assert_eq!(lock_seconds(LOGIN_FREE_ATTEMPTS), LOGIN_LOCK_BASE_SECS);
+        assert_eq!(
+            lock_seconds(LOGIN_FREE_ATTEMPTS + 2),
+            LOGIN_LOCK_BASE_SECS * 4
+        );
+        assert_eq!(lock_seconds(1000), LOGIN_LOCK_MAX_SECS);
+    }
+}
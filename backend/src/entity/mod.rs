@@ -1,10 +1,22 @@
+pub mod acl;
+pub mod api_token;
+pub mod audit;
 pub mod copy_move_task;
 pub mod error;
+pub mod fetch_task;
 pub mod file;
+pub mod group;
 pub mod hidden;
+pub mod job;
+pub mod login_attempt;
+pub mod quota;
+pub mod recovery_code;
 pub mod request;
 pub mod reset_password;
 pub mod response;
+pub mod session;
+pub mod share;
+pub mod share_access;
 pub mod site;
 pub mod upload_task;
 pub mod user;
@@ -0,0 +1,208 @@
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::Serialize;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+// A byte limit on a directory and all the files under it.
+// The path is relative to the storage, with an empty string for the root.
+#[derive(Serialize, FromRow, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct DirQuota {
+    pub quota_id: i64,
+    pub path: String,
+    pub max_bytes: i64,
+}
+
+// The owner of a file, whose size counts towards the quota of the user.
+#[derive(FromRow, Debug)]
+pub struct UploadedFile {
+    pub path: String,
+    pub user_id: i64,
+    pub size: i64,
+    pub created_at: i64,
+}
+
+#[derive(FromRow, Debug)]
+struct Usage {
+    bytes: i64,
+}
+
+impl DirQuota {
+    pub fn new(path: &str, max_bytes: i64) -> Self {
+        Self {
+            quota_id: 0,
+            path: path.to_owned(),
+            max_bytes,
+        }
+    }
+
+    pub async fn upsert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "insert into DIR_QUOTA (path, max_bytes) values (?1, ?2) on conflict(path) do update set max_bytes = ?2";
+        let query = Query::new(sql, args![&self.path, self.max_bytes]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(path: &str, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from DIR_QUOTA where path = ?1";
+        let query = Query::new(sql, args![path]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from DIR_QUOTA";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    // Same as `Hidden::update_all_sub_path_query()`.
+    pub async fn update_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        current_path: &str,
+        new_path: &str,
+    ) -> AnyResult<()> {
+        update_sub_path("DIR_QUOTA", tx, current_path, new_path).await
+    }
+
+    pub async fn delete_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        path: &str,
+    ) -> AnyResult<()> {
+        delete_sub_path("DIR_QUOTA", tx, path).await
+    }
+
+    pub async fn find_all(conn: &mut PoolConnection<Sqlite>) -> AnyResult<Vec<Self>> {
+        let sql = "select * from DIR_QUOTA order by path";
+        let query = Query::new(sql, vec![]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+impl UploadedFile {
+    pub fn new(path: &str, user_id: i64, size: i64) -> Self {
+        Self {
+            path: path.to_owned(),
+            user_id,
+            size,
+            created_at: util::get_utc_seconds(),
+        }
+    }
+
+    // Overwriting a file passes the ownership to the new uploader.
+    pub async fn upsert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "insert or replace into UPLOADED_FILE (path, user_id, size, created_at) values (?1, ?2, ?3, ?4)";
+        let query = Query::new(
+            sql,
+            args![&self.path, self.user_id, self.size, self.created_at],
+        );
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_user_files_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from UPLOADED_FILE where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from UPLOADED_FILE";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        current_path: &str,
+        new_path: &str,
+    ) -> AnyResult<()> {
+        update_sub_path("UPLOADED_FILE", tx, current_path, new_path).await
+    }
+
+    pub async fn delete_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        path: &str,
+    ) -> AnyResult<()> {
+        delete_sub_path("UPLOADED_FILE", tx, path).await
+    }
+
+    pub async fn find_user_usage(
+        user_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<i64> {
+        let sql = "select coalesce(sum(size), 0) as bytes from UPLOADED_FILE where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+        let usage: Option<Usage> = db::fetch_single(query, conn).await?;
+
+        Ok(usage.map(|u| u.bytes).unwrap_or(0))
+    }
+}
+
+// The table name is never from user input.
+async fn update_sub_path(
+    table: &str,
+    tx: &mut Transaction<'_, Sqlite>,
+    current_path: &str,
+    new_path: &str,
+) -> AnyResult<()> {
+    let sql = format!("update {} set path = ?1 where path = ?2", table);
+    let query = Query::new(&sql, args![new_path, current_path]);
+
+    db::execute(query, tx).await?;
+
+    let sql = format!(
+        "update {} set path = replace(substr(path, ?1, ?2), ?3, ?4) || substr(path, ?5) where path like ?6",
+        table
+    );
+    let query = Query::new(
+        &sql,
+        args![
+            1,
+            current_path.len() + 2,
+            format!("{}/", current_path),
+            format!("{}/", new_path),
+            current_path.len() + 3,
+            format!("{}/%", current_path)
+        ],
+    );
+
+    db::execute(query, tx).await?;
+
+    Ok(())
+}
+
+async fn delete_sub_path(
+    table: &str,
+    tx: &mut Transaction<'_, Sqlite>,
+    path: &str,
+) -> AnyResult<()> {
+    let sql = format!("delete from {} where path = ?1", table);
+    let query = Query::new(&sql, args![path]);
+    db::execute(query, tx).await?;
+
+    let sql = format!("delete from {} where path like ?1", table);
+    let query = Query::new(&sql, args![format!("{}/%", path)]);
+    db::execute(query, tx).await?;
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+// One-time codes to log in when the authenticator device is lost. Only the hashes of the
+// codes are saved, so a code is found by its hash and then marked used by its id.
+#[derive(FromRow, Debug)]
+pub struct RecoveryCode {
+    pub code_id: i64,
+}
+
+#[derive(FromRow, Debug)]
+struct RecoveryCodeCount {
+    total: i64,
+}
+
+impl RecoveryCode {
+    // Replace all previous codes of the user and return the new plain codes.
+    pub async fn regenerate_query(
+        user_id: i64,
+        secret: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<Vec<String>> {
+        Self::delete_user_codes_query(user_id, tx).await?;
+
+        let mut codes = vec![];
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let code = util::generate_secret_key(RECOVERY_CODE_LENGTH).to_lowercase();
+            let sql = "insert into RECOVERY_CODE (user_id, code_hash) values (?1, ?2)";
+            let query = Query::new(sql, args![user_id, util::sha256(&code, secret)]);
+            db::execute(query, tx).await?;
+            codes.push(code);
+        }
+
+        Ok(codes)
+    }
+
+    pub async fn delete_user_codes_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from RECOVERY_CODE where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn mark_used_query(code_id: i64, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "update RECOVERY_CODE set used_at = ?1 where code_id = ?2";
+        let query = Query::new(sql, args![util::get_utc_seconds(), code_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_unused(
+        user_id: i64,
+        code: &str,
+        secret: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let code = code.trim().to_lowercase();
+        let sql = "select code_id from RECOVERY_CODE where user_id = ?1 and code_hash = ?2 and used_at = 0";
+        let query = Query::new(sql, args![user_id, util::sha256(&code, secret)]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    pub async fn count_unused(user_id: i64, conn: &mut PoolConnection<Sqlite>) -> AnyResult<i64> {
+        let sql = "select count(*) as total from RECOVERY_CODE where user_id = ?1 and used_at = 0";
+        let query = Query::new(sql, args![user_id]);
+        let count: Option<RecoveryCodeCount> = db::fetch_single(query, conn).await?;
+
+        Ok(count.map(|c| c.total).unwrap_or(0))
+    }
+}
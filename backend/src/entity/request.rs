@@ -1,10 +1,22 @@
+use super::acl::AclSubject;
+use super::api_token::TokenScope;
+use super::share::ShareType;
+use super::upload_task::ConflictPolicy;
 use rocket::serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct GenerateLinkRequest {
     pub path: String,
+    // Zero or absent for a link that never expires.
+    #[serde(default)]
     pub expire: i64,
+    pub password: Option<String>,
+    pub max_downloads: Option<i64>,
+    pub share_type: Option<ShareType>,
+    // Limits of upload shares.
+    pub max_file_size: Option<i64>,
+    pub max_files: Option<i64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -25,6 +37,8 @@ pub struct UpdateSiteRequest {
     pub language: String,
     pub update_freq: String,
     pub allow_guest: bool,
+    // Keep the current setting if not provided.
+    pub require_admin_2fa: Option<bool>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -49,6 +63,13 @@ pub struct UploadRequest {
     pub size: u64,
     pub target: String,
     pub hash: String,
+    // Fail if absent, so existing files are never replaced by accident.
+    pub conflict: Option<ConflictPolicy>,
+    // The dir of the file relative to the target for a folder upload, e.g. "photos/2021",
+    // created if missing.
+    pub relative_dir: Option<String>,
+    // Chosen by the client for all the files of a folder upload, to cancel them at once.
+    pub batch_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -62,7 +83,10 @@ pub struct UploadSliceRequest {
 #[derive(Deserialize, Debug)]
 #[serde(crate = "rocket::serde")]
 pub struct CancelUploadRequest {
+    #[serde(default)]
     pub uuids: Vec<String>,
+    #[serde(default)]
+    pub batch_ids: Vec<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -82,6 +106,7 @@ pub struct RenameFileRequest {
 #[serde(crate = "rocket::serde")]
 pub struct SetFileVisibilityRequest {
     pub visible: bool,
+    pub group_id: Option<i64>,
 }
 
 #[derive(Deserialize)]
@@ -99,3 +124,116 @@ pub struct ResetPasswordRequest {
     pub username: String,
     pub password: String,
 }
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    pub permission: i8,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct UpdatePermissionRequest {
+    pub permission: i8,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct UpdateUserStatusRequest {
+    pub disabled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct UpdateHomeDirRequest {
+    pub home_dir: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scope: TokenScope,
+    // Never expire if not provided.
+    pub expire_days: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DisableTwoFactorRequest {
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct AclRequest {
+    pub path: String,
+    pub subject_type: AclSubject,
+    pub subject_id: i64,
+    pub read: bool,
+    pub write: bool,
+    pub delete: bool,
+    pub share: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct GroupRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct UpdateQuotaRequest {
+    pub max_bytes: i64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct DirQuotaRequest {
+    pub path: String,
+    pub max_bytes: i64,
+}
+
+#[derive(FromForm, Debug, Default)]
+pub struct AuditFilter {
+    pub user_id: Option<i64>,
+    pub action: Option<String>,
+    pub path: Option<String>,
+    pub result: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+// The target dir is encoded as in `pre_upload`. The filename is taken from the url if
+// absent, and a zero or absent max size means no limit but the free space.
+#[derive(Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct FetchRequest {
+    pub url: String,
+    pub target: String,
+    pub filename: Option<String>,
+    pub max_size: Option<u64>,
+    pub conflict: Option<ConflictPolicy>,
+}
+
+#[derive(FromForm, Debug, Default)]
+pub struct PutFileOptions {
+    pub conflict: Option<String>,
+    pub hash: Option<String>,
+}
+
+// The password of a share posted from the share page, as a browser following a link
+// can't send the header.
+#[derive(FromForm, Debug, Default)]
+pub struct SharePasswordForm {
+    pub password: String,
+}
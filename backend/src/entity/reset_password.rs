@@ -60,9 +60,16 @@ impl ResetPassword {
     pub async fn remove_user_reset_password_files(
         &self,
         conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<()> {
+        Self::remove_reset_password_files_by_username(&self.username, conn).await
+    }
+
+    pub async fn remove_reset_password_files_by_username(
+        username: &str,
+        conn: &mut PoolConnection<Sqlite>,
     ) -> AnyResult<()> {
         let sql = "select * from RESET where username = ?1";
-        let query = Query::new(sql, args![self.username]);
+        let query = Query::new(sql, args![username]);
         let resets: Vec<ResetPassword> = db::fetch_multiple(query, conn).await?;
         let temp_path = util::get_data_temp_path();
 
@@ -2,8 +2,18 @@ use crate::service::range::RangedFile;
 use crate::util::constants::{DEFAULT_APP_NAME, DEFAULT_LANGUAGE, DEFAULT_UPDATE_FREQ, VERSION};
 use rocket::fs::NamedFile;
 use rocket::serde::Serialize;
+use std::path::Path;
 
+use super::api_token::ApiToken;
+use super::audit::Audit;
+use super::copy_move_task::{CopyMoveTask, CopyMoveTaskStatus};
+use super::job::{self, Job};
+use super::session::Session;
+use super::share::Share;
+use super::share_access::ShareAccess;
 use super::site::Site;
+use super::upload_task::UploadTask;
+use super::user::User;
 
 #[derive(Responder)]
 pub enum FileResponse {
@@ -25,6 +35,149 @@ pub struct LoginResponse {
     pub username: String,
     pub permission: i8,
     pub expire: usize,
+    // Present when a second login step is required before tokens are issued.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor: Option<TwoFactorStep>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum TwoFactorStep {
+    Verify,
+    Setup,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorStatusResponse {
+    pub enabled: bool,
+    pub required: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserResponse {
+    pub user_id: i64,
+    pub username: String,
+    pub permission: i8,
+    pub disabled: bool,
+    pub home_dir: String,
+    pub created_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ShareResponse {
+    pub share_id: i64,
+    pub slug: String,
+    pub path: String,
+    pub user_id: i64,
+    pub has_password: bool,
+    pub max_downloads: i64,
+    pub download_count: i64,
+    pub created_at: i64,
+    pub expire_at: i64,
+    pub share_type: String,
+    pub max_file_size: i64,
+    pub max_files: i64,
+    pub upload_count: i64,
+}
+
+// Downloads are the requests starting from the first byte, or for the zip of a dir.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ShareStatsResponse {
+    pub share: ShareResponse,
+    pub requests: i64,
+    pub downloads: i64,
+    pub completed: i64,
+    pub partial: i64,
+    pub bytes_served: i64,
+    pub unique_ips: i64,
+    pub last_access_at: i64,
+    pub accesses: Vec<ShareAccess>,
+}
+
+// The name the file is saved as, which differs from the uploaded one when renamed
+// for a conflict.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UploadResultResponse {
+    pub filename: String,
+    pub skipped: bool,
+}
+
+// The hash is of the stored file, in the format of `FileHash`.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PutFileResponse {
+    pub filename: String,
+    pub size: u64,
+    pub hash: String,
+    pub skipped: bool,
+}
+
+// The unfinished uploads of a user, to be resumed by the client.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UploadTaskResponse {
+    pub uuid: String,
+    pub filename: String,
+    pub size: i64,
+    pub hash: String,
+    pub finished_slices: i64,
+    pub conflict: String,
+    pub batch_id: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// A queued or running copy or move of a user, or one from the history with `finished_at` set.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobResponse {
+    pub uuid: String,
+    pub is_copy: bool,
+    pub status: String,
+    pub source: String,
+    pub target: String,
+    pub progress: f64,
+    pub error: String,
+    pub created_at: i64,
+    pub finished_at: i64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiTokenResponse {
+    pub token_id: i64,
+    pub name: String,
+    pub scope: String,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expire_at: i64,
+    // The plain token is only returned once when it is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionResponse {
+    pub session_id: String,
+    pub device: String,
+    pub ip: String,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub current: bool,
 }
 
 #[derive(Serialize, Debug)]
@@ -46,6 +199,7 @@ pub struct SiteFullResponse {
     pub update_freq: String,
     pub storage: String,
     pub allow_guest: bool,
+    pub require_admin_2fa: bool,
 }
 
 impl From<Site> for SiteBriefResponse {
@@ -60,6 +214,142 @@ impl From<Site> for SiteBriefResponse {
     }
 }
 
+impl From<User> for UserResponse {
+    fn from(u: User) -> Self {
+        Self {
+            user_id: u.user_id,
+            disabled: u.is_disabled(),
+            username: u.username,
+            permission: u.permission,
+            home_dir: u.home_dir,
+            created_at: u.created_at,
+        }
+    }
+}
+
+impl From<ApiToken> for ApiTokenResponse {
+    fn from(t: ApiToken) -> Self {
+        Self {
+            token_id: t.token_id,
+            name: t.name,
+            scope: t.scope,
+            created_at: t.created_at,
+            last_used_at: t.last_used_at,
+            expire_at: t.expire_at,
+            token: None,
+        }
+    }
+}
+
+impl From<Share> for ShareResponse {
+    fn from(s: Share) -> Self {
+        Self {
+            share_id: s.share_id,
+            has_password: s.has_password(),
+            slug: s.slug,
+            path: s.path,
+            user_id: s.user_id,
+            max_downloads: s.max_downloads,
+            download_count: s.download_count,
+            created_at: s.created_at,
+            expire_at: s.expire_at,
+            share_type: s.share_type,
+            max_file_size: s.max_file_size,
+            max_files: s.max_files,
+            upload_count: s.upload_count,
+        }
+    }
+}
+
+impl From<UploadTask> for UploadTaskResponse {
+    fn from(t: UploadTask) -> Self {
+        Self {
+            uuid: t.uuid,
+            filename: t.filename,
+            size: t.size,
+            hash: t.hash,
+            finished_slices: t.finished_slices,
+            conflict: t.conflict,
+            batch_id: t.batch_id,
+            created_at: t.created_at,
+            updated_at: t.updated_at,
+        }
+    }
+}
+
+impl JobResponse {
+    pub fn from_task(t: CopyMoveTask, storage: &Path) -> Self {
+        Self {
+            source: job::storage_relative(&t.source, storage),
+            target: job::storage_relative(&t.target, storage),
+            uuid: t.uuid,
+            is_copy: t.is_copy,
+            status: t.status.as_str().to_owned(),
+            progress: t.progress,
+            error: t.error,
+            created_at: t.created_at,
+            finished_at: 0,
+        }
+    }
+}
+
+impl From<Job> for JobResponse {
+    fn from(j: Job) -> Self {
+        let progress = if j.status == CopyMoveTaskStatus::Success.as_str() {
+            1.0
+        } else {
+            0.0
+        };
+
+        Self {
+            uuid: j.uuid,
+            is_copy: j.kind == "copy",
+            status: j.status,
+            source: j.source,
+            target: j.target,
+            progress,
+            error: j.error,
+            created_at: j.created_at,
+            finished_at: j.finished_at,
+        }
+    }
+}
+
+impl ShareStatsResponse {
+    // The accesses are sorted with the latest first.
+    pub fn from_accesses(share: Share, accesses: Vec<ShareAccess>) -> Self {
+        let mut ips: Vec<&str> = accesses.iter().map(|a| a.ip.as_str()).collect();
+        ips.sort_unstable();
+        ips.dedup();
+        let completed = accesses.iter().filter(|a| a.completed > 0).count() as i64;
+
+        Self {
+            share: share.into(),
+            requests: accesses.len() as i64,
+            downloads: accesses.iter().filter(|a| a.range_start == 0).count() as i64,
+            completed,
+            partial: accesses.len() as i64 - completed,
+            bytes_served: accesses.iter().map(|a| a.bytes).sum(),
+            unique_ips: ips.len() as i64,
+            last_access_at: accesses.first().map(|a| a.created_at).unwrap_or(0),
+            accesses,
+        }
+    }
+}
+
+impl SessionResponse {
+    pub fn from_session(s: Session, current: bool) -> Self {
+        Self {
+            session_id: s.session_id,
+            device: s.device,
+            ip: s.ip,
+            created_at: s.created_at,
+            last_used_at: s.last_used_at,
+            current,
+        }
+    }
+}
+
 impl Default for SiteBriefResponse {
     fn default() -> Self {
         Self {
@@ -81,6 +371,7 @@ impl From<Site> for SiteFullResponse {
             storage: s.storage,
             update_freq: s.update_freq,
             allow_guest: s.allow_guest > 0,
+            require_admin_2fa: s.require_admin_2fa > 0,
         }
     }
 }
@@ -94,6 +385,47 @@ impl Default for SiteFullResponse {
             storage: String::new(),
             update_freq: DEFAULT_UPDATE_FREQ.to_owned(),
             allow_guest: false,
+            require_admin_2fa: false,
         }
     }
 }
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UsageResponse {
+    pub used_bytes: i64,
+    pub quota_bytes: i64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserUsageResponse {
+    pub user_id: i64,
+    pub username: String,
+    pub used_bytes: i64,
+    pub quota_bytes: i64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DirUsageResponse {
+    pub path: String,
+    pub used_bytes: u64,
+    pub max_bytes: i64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct QuotaResponse {
+    pub users: Vec<UserUsageResponse>,
+    pub dirs: Vec<DirUsageResponse>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AuditPageResponse {
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+    pub entries: Vec<Audit>,
+}
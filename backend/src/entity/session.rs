@@ -0,0 +1,138 @@
+use crate::{
+    args,
+    service::client::ClientInfo,
+    util::{
+        self,
+        constants::REFRESH_TOKEN_DAYS,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+#[derive(FromRow, Debug)]
+pub struct Session {
+    pub session_id: String,
+    pub user_id: i64,
+    pub device: String,
+    pub ip: String,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub expire_at: i64,
+}
+
+impl Session {
+    pub fn new(user_id: i64, client: &ClientInfo) -> Self {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        let current_timestamp = util::get_utc_seconds();
+
+        Self {
+            session_id: uuid,
+            user_id,
+            device: client.user_agent.to_owned(),
+            ip: client.ip.to_owned(),
+            created_at: current_timestamp,
+            last_used_at: current_timestamp,
+            expire_at: Self::next_expire_at(),
+        }
+    }
+
+    // Session lives as long as the refresh token issued with it.
+    fn next_expire_at() -> i64 {
+        util::get_utc_seconds() + REFRESH_TOKEN_DAYS * 24 * 60 * 60
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "insert into SESSION (session_id, user_id, device, ip, created_at, last_used_at, expire_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+        let query = Query::new(
+            sql,
+            args![
+                &self.session_id,
+                self.user_id,
+                &self.device,
+                &self.ip,
+                self.created_at,
+                self.last_used_at,
+                self.expire_at
+            ],
+        );
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    // Record the latest usage and extend the session along with the new refresh token.
+    pub async fn touch_query(
+        session_id: &str,
+        client: &ClientInfo,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql =
+            "update SESSION set ip = ?1, last_used_at = ?2, expire_at = ?3 where session_id = ?4";
+        let query = Query::new(
+            sql,
+            args![
+                &client.ip,
+                util::get_utc_seconds(),
+                Self::next_expire_at(),
+                session_id
+            ],
+        );
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(
+        session_id: &str,
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from SESSION where session_id = ?1 and user_id = ?2";
+        let query = Query::new(sql, args![session_id, user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_user_sessions_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from SESSION where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_expired_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from SESSION where expire_at < ?1";
+        let query = Query::new(sql, args![util::get_utc_seconds()]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_valid(
+        session_id: &str,
+        user_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql =
+            "select * from SESSION where session_id = ?1 and user_id = ?2 and expire_at >= ?3";
+        let query = Query::new(sql, args![session_id, user_id, util::get_utc_seconds()]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    pub async fn find_user_sessions(
+        user_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from SESSION where user_id = ?1 and expire_at >= ?2 order by last_used_at desc";
+        let query = Query::new(sql, args![user_id, util::get_utc_seconds()]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
@@ -0,0 +1,346 @@
+use super::request::GenerateLinkRequest;
+use crate::{
+    args,
+    util::{
+        self,
+        constants::SHARE_SLUG_LENGTH,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use bcrypt::{hash, verify, DEFAULT_COST};
+use rocket::serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+// A download share is for a file or a dir, and an upload share lets visitors
+// drop files into a dir without seeing what is inside.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum ShareType {
+    Download,
+    Upload,
+}
+
+impl ShareType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareType::Download => "download",
+            ShareType::Upload => "upload",
+        }
+    }
+}
+
+// The path is relative to the storage, and the password is saved as a bcrypt hash.
+// A zero `expire_at` or limit means no limit.
+#[derive(FromRow, Debug, Clone)]
+pub struct Share {
+    pub share_id: i64,
+    pub slug: String,
+    pub path: String,
+    pub user_id: i64,
+    pub password: String,
+    pub max_downloads: i64,
+    pub download_count: i64,
+    pub revoked: i8,
+    pub created_at: i64,
+    pub expire_at: i64,
+    pub share_type: String,
+    pub max_file_size: i64,
+    pub max_files: i64,
+    pub upload_count: i64,
+}
+
+impl Share {
+    pub fn new(path: &str, user_id: i64, req: &GenerateLinkRequest) -> AnyResult<Self> {
+        let password = match req.password.as_deref() {
+            Some(p) if !p.is_empty() => hash(p, DEFAULT_COST)?,
+            _ => String::new(),
+        };
+        let share_type = req.share_type.unwrap_or(ShareType::Download);
+
+        Ok(Self {
+            share_id: 0,
+            slug: util::generate_secret_key(SHARE_SLUG_LENGTH),
+            path: path.to_owned(),
+            user_id,
+            password,
+            max_downloads: req.max_downloads.unwrap_or(0).max(0),
+            download_count: 0,
+            revoked: 0,
+            created_at: util::get_utc_seconds(),
+            expire_at: req.expire.max(0),
+            share_type: share_type.as_str().to_owned(),
+            max_file_size: req.max_file_size.unwrap_or(0).max(0),
+            max_files: req.max_files.unwrap_or(0).max(0),
+            upload_count: 0,
+        })
+    }
+
+    pub fn is_upload(&self) -> bool {
+        self.share_type == ShareType::Upload.as_str()
+    }
+
+    pub fn has_password(&self) -> bool {
+        !self.password.is_empty()
+    }
+
+    pub fn is_active(&self, now: i64) -> bool {
+        self.is_open(now) && self.within_limit()
+    }
+
+    // Neither revoked nor expired, whatever the limits.
+    pub fn is_open(&self, now: i64) -> bool {
+        self.revoked == 0 && (self.expire_at == 0 || self.expire_at > now)
+    }
+
+    pub fn within_limit(&self) -> bool {
+        if self.is_upload() {
+            self.max_files == 0 || self.upload_count < self.max_files
+        } else {
+            self.max_downloads == 0 || self.download_count < self.max_downloads
+        }
+    }
+
+    // Zero-sized files are fine, as long as they fit in the limit.
+    pub fn accepts_file_size(&self, size: u64) -> bool {
+        self.max_file_size == 0 || size <= self.max_file_size as u64
+    }
+
+    pub fn check_password(&self, password: Option<&str>) -> bool {
+        if !self.has_password() {
+            return true;
+        }
+
+        match password {
+            Some(p) => verify(p, &self.password).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into SHARE (slug, path, user_id, password, max_downloads, created_at, expire_at, share_type, max_file_size, max_files) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+        let query = Query::new(
+            sql,
+            args![
+                &self.slug,
+                &self.path,
+                self.user_id,
+                &self.password,
+                self.max_downloads,
+                self.created_at,
+                self.expire_at,
+                &self.share_type,
+                self.max_file_size,
+                self.max_files
+            ],
+        );
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    pub async fn revoke_query(share_id: i64, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "update SHARE set revoked = 1 where share_id = ?1";
+        let query = Query::new(sql, args![share_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn increase_download_query(
+        share_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update SHARE set download_count = download_count + 1 where share_id = ?1";
+        let query = Query::new(sql, args![share_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn increase_upload_query(
+        share_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update SHARE set upload_count = upload_count + 1 where share_id = ?1";
+        let query = Query::new(sql, args![share_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_user_shares_query(
+        user_id: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from SHARE where user_id = ?1";
+        let query = Query::new(sql, args![user_id]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from SHARE";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    // Same as `Hidden::update_all_sub_path_query()`.
+    pub async fn update_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        current_path: &str,
+        new_path: &str,
+    ) -> AnyResult<()> {
+        let sql = "update SHARE set path = ?1 where path = ?2";
+        let query = Query::new(sql, args![new_path, current_path]);
+
+        db::execute(query, tx).await?;
+
+        let sql =
+            "update SHARE set path = replace(substr(path, ?1, ?2), ?3, ?4) || substr(path, ?5) where path like ?6";
+        let query = Query::new(
+            sql,
+            args![
+                1,
+                current_path.len() + 2,
+                format!("{}/", current_path),
+                format!("{}/", new_path),
+                current_path.len() + 3,
+                format!("{}/%", current_path)
+            ],
+        );
+
+        db::execute(query, tx).await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_all_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        path: &str,
+    ) -> AnyResult<()> {
+        let sql = "delete from SHARE where path = ?1";
+        let query = Query::new(sql, args![path]);
+        db::execute(query, tx).await?;
+
+        let sql = "delete from SHARE where path like ?1";
+        let query = Query::new(sql, args![format!("{}/%", path)]);
+        db::execute(query, tx).await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(
+        share_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql = "select * from SHARE where share_id = ?1";
+        let query = Query::new(sql, args![share_id]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    pub async fn find_by_slug(
+        slug: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql = "select * from SHARE where slug = ?1";
+        let query = Query::new(sql, args![slug]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    // Only the shares that are neither revoked, expired nor used up.
+    // All users' shares are returned if `user_id` is `None`.
+    pub async fn find_active(
+        user_id: Option<i64>,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let mut sql = String::from(
+            "select * from SHARE where revoked = 0 and (expire_at = 0 or expire_at > ?1) and (share_type = 'upload' or max_downloads = 0 or download_count < max_downloads) and (share_type = 'download' or max_files = 0 or upload_count < max_files)",
+        );
+        let mut args = args![util::get_utc_seconds()];
+        if let Some(uid) = user_id {
+            sql.push_str(" and user_id = ?2");
+            args.push(uid.to_string());
+        }
+        sql.push_str(" order by created_at desc");
+
+        let query = Query::new(&sql, args);
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link_request(share_type: ShareType, password: Option<&str>) -> GenerateLinkRequest {
+        GenerateLinkRequest {
+            path: String::new(),
+            expire: 0,
+            password: password.map(|p| p.to_owned()),
+            max_downloads: None,
+            share_type: Some(share_type),
+            max_file_size: None,
+            max_files: None,
+        }
+    }
+
+    #[test]
+    fn test_share_active() {
+        let mut req = link_request(ShareType::Download, None);
+        req.expire = 100;
+        req.max_downloads = Some(2);
+        let mut share = Share::new("docs/a.txt", 1, &req).unwrap();
+        assert!(share.is_active(99));
+        assert!(!share.is_active(100));
+
+        share.download_count = 2;
+        assert!(!share.is_active(0));
+        assert!(share.is_open(0) && !share.within_limit());
+
+        share.download_count = 1;
+        share.revoked = 1;
+        assert!(!share.is_active(0));
+
+        let req = link_request(ShareType::Download, None);
+        let share = Share::new("docs/a.txt", 1, &req).unwrap();
+        assert!(share.is_active(i64::MAX));
+    }
+
+    #[test]
+    fn test_upload_share_limits() {
+        let mut req = link_request(ShareType::Upload, None);
+        req.max_downloads = Some(1);
+        req.max_files = Some(2);
+        req.max_file_size = Some(10);
+        let mut share = Share::new("docs", 1, &req).unwrap();
+        assert!(share.is_upload());
+        assert!(share.accepts_file_size(10));
+        assert!(!share.accepts_file_size(11));
+
+        // Downloads never count for an upload share.
+        share.download_count = 5;
+        share.upload_count = 1;
+        assert!(share.is_active(0));
+
+        share.upload_count = 2;
+        assert!(!share.is_active(0));
+    }
+
+    #[test]
+    fn test_share_password() {
+        let req = link_request(ShareType::Download, Some(""));
+        let share = Share::new("docs/a.txt", 1, &req).unwrap();
+        assert!(share.check_password(None));
+
+        let req = link_request(ShareType::Download, Some("secret"));
+        let share = Share::new("docs/a.txt", 1, &req).unwrap();
+        assert!(!share.check_password(None));
+        assert!(!share.check_password(Some("wrong")));
+        assert!(share.check_password(Some("secret")));
+    }
+}
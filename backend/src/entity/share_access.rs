@@ -0,0 +1,147 @@
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::Serialize;
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+
+// One record for every download through a share. The path is relative to the
+// shared dir, and empty for the shared file itself or the zip of the shared dir.
+// The range is inclusive. A download is completed when the whole file is served,
+// and partial for a range in the middle of it. The size of a zip is unknown, so
+// no bytes are recorded for it.
+#[derive(Serialize, FromRow, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct ShareAccess {
+    pub access_id: i64,
+    pub share_id: i64,
+    pub ip: String,
+    pub user_agent: String,
+    pub path: String,
+    pub range_start: i64,
+    pub range_end: i64,
+    pub bytes: i64,
+    pub completed: i8,
+    pub created_at: i64,
+}
+
+impl ShareAccess {
+    pub fn new(
+        share_id: i64,
+        ip: &str,
+        user_agent: &str,
+        path: &str,
+        served: Option<(u64, u64)>,
+        completed: bool,
+    ) -> Self {
+        let (range_start, range_end, bytes) = match served {
+            Some((start, end)) => (start as i64, end as i64, (end + 1 - start) as i64),
+            None => (0, 0, 0),
+        };
+
+        Self {
+            access_id: 0,
+            share_id,
+            ip: ip.to_owned(),
+            user_agent: user_agent.to_owned(),
+            path: path.to_owned(),
+            range_start,
+            range_end,
+            bytes,
+            completed: completed as i8,
+            created_at: util::get_utc_seconds(),
+        }
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into SHARE_ACCESS (share_id, ip, user_agent, path, range_start, range_end, bytes, completed, created_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+        let query = Query::new(
+            sql,
+            args![
+                self.share_id,
+                &self.ip,
+                &self.user_agent,
+                &self.path,
+                self.range_start,
+                self.range_end,
+                self.bytes,
+                self.completed,
+                self.created_at
+            ],
+        );
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    // Called after shares are deleted.
+    pub async fn delete_orphans_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from SHARE_ACCESS where share_id not in (select share_id from SHARE)";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn has_visited(
+        share_id: i64,
+        ip: &str,
+        user_agent: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<bool> {
+        let sql = "select * from SHARE_ACCESS where share_id = ?1 and ip = ?2 and user_agent = ?3 limit 1";
+        let query = Query::new(sql, args![share_id, ip, user_agent]);
+        let access: Option<Self> = db::fetch_single(query, conn).await?;
+
+        Ok(access.is_some())
+    }
+
+    pub async fn find_by_share(
+        share_id: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from SHARE_ACCESS where share_id = ?1 order by created_at desc, access_id desc";
+        let query = Query::new(sql, args![share_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+// Get the inclusive range served for the requested one, and whether it is the whole file.
+// An end of 0 means the end of the file, same as `RangedFile`.
+pub fn served_range(range: Option<(u64, u64)>, size: u64) -> Option<(u64, u64, bool)> {
+    if size == 0 {
+        return None;
+    }
+
+    let (start, end) = match range {
+        Some((start, 0)) => (start, size - 1),
+        Some((start, end)) => (start, end.min(size - 1)),
+        None => (0, size - 1),
+    };
+
+    if start > end {
+        return None;
+    }
+
+    Some((start, end, start == 0 && end == size - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_served_range() {
+        assert_eq!(served_range(None, 10), Some((0, 9, true)));
+        assert_eq!(served_range(Some((0, 0)), 10), Some((0, 9, true)));
+        assert_eq!(served_range(Some((0, 4)), 10), Some((0, 4, false)));
+        assert_eq!(served_range(Some((5, 0)), 10), Some((5, 9, false)));
+        assert_eq!(served_range(Some((5, 20)), 10), Some((5, 9, false)));
+        assert_eq!(served_range(Some((12, 0)), 10), None);
+        assert_eq!(served_range(None, 0), None);
+    }
+}
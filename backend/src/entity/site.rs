@@ -21,6 +21,7 @@ pub struct Site {
     pub created_at: i64,
     pub updated_at: i64,
     pub allow_guest: i8,
+    pub require_admin_2fa: i8,
 }
 
 impl Site {
@@ -41,6 +42,7 @@ impl Site {
             created_at,
             updated_at: created_at,
             allow_guest: 0,
+            require_admin_2fa: 0,
         }
     }
 
@@ -74,7 +76,7 @@ impl Site {
     }
 
     pub async fn update(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
-        let sql = "update SITE set name = ?1, version = ?2, storage = ?3, secret = ?4, created_at = ?5, language = ?6, update_freq = ?7, updated_at = ?8, allow_guest = ?9";
+        let sql = "update SITE set name = ?1, version = ?2, storage = ?3, secret = ?4, created_at = ?5, language = ?6, update_freq = ?7, updated_at = ?8, allow_guest = ?9, require_admin_2fa = ?10";
         let query = Query::new(
             sql,
             args![
@@ -86,7 +88,8 @@ impl Site {
                 &self.language,
                 &self.update_freq,
                 &self.updated_at,
-                &self.allow_guest
+                &self.allow_guest,
+                &self.require_admin_2fa
             ],
         );
 
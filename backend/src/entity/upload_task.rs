@@ -1,32 +1,274 @@
+use super::error::Error;
 use super::request::UploadRequest;
-use std::path::PathBuf;
+use crate::{
+    args,
+    util::{
+        self,
+        db::{self, Query},
+    },
+};
+use anyhow::Result as AnyResult;
+use rocket::serde::{Deserialize, Serialize};
+use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone)]
+// What to do when a file with the same name exists in the target dir. `Fail` is checked
+// before any slice is sent, and again when the upload is finished.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Fail,
+    Overwrite,
+    Rename,
+    Skip,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Fail => "fail",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Skip => "skip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fail" => Some(ConflictPolicy::Fail),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "rename" => Some(ConflictPolicy::Rename),
+            "skip" => Some(ConflictPolicy::Skip),
+            _ => None,
+        }
+    }
+
+    // Return the path to save the file to, or `None` to skip it. A dir of the same name
+    // is never replaced.
+    pub fn resolve(&self, dir: &Path, filename: &str) -> Result<Option<PathBuf>, Error> {
+        let target = dir.join(filename);
+        if !target.exists() {
+            return Ok(Some(target));
+        }
+
+        match self {
+            ConflictPolicy::Fail => Err(Error::Conflict),
+            ConflictPolicy::Overwrite if target.is_dir() => Err(Error::Conflict),
+            ConflictPolicy::Overwrite => Ok(Some(target)),
+            ConflictPolicy::Rename => Ok(Some(unique_file_path(dir, filename))),
+            ConflictPolicy::Skip => Ok(None),
+        }
+    }
+}
+
+fn unique_file_path(dir: &Path, filename: &str) -> PathBuf {
+    let mut target = dir.join(filename);
+    let mut n = 1;
+    while target.exists() {
+        target = dir.join(numbered_filename(filename, n));
+        n += 1;
+    }
+
+    target
+}
+
+// "a.txt" becomes "a (1).txt", and the extension is kept for files like ".bashrc".
+fn numbered_filename(filename: &str, n: u64) -> String {
+    match filename.rfind('.') {
+        Some(i) if i > 0 => format!("{} ({}){}", &filename[..i], n, &filename[i..]),
+        _ => format!("{} ({})", filename, n),
+    }
+}
+
+// Saved in the db so uploads can be resumed after the server restarts, while the
+// slices are kept in the temp dir named by the uuid. `updated_at` is refreshed by
+// every slice, and the tasks idle for too long are removed by the garbage collector.
+#[derive(FromRow, Debug, Clone)]
 pub struct UploadTask {
     pub uuid: String,
+    #[sqlx(rename = "user_id")]
     pub userid: i64,
+    // Set for the anonymous uploads through an upload share, owned by its creator.
+    pub share_id: i64,
     pub filename: String,
-    pub size: u64,
-    pub dir: PathBuf,
+    pub size: i64,
+    pub dir: String,
     pub hash: String,
-    pub finished_slices: u64,
+    pub finished_slices: i64,
+    pub conflict: String,
+    // Shared by the files of a folder upload, empty for a single file.
+    pub batch_id: String,
+    pub created_at: i64,
+    pub updated_at: i64,
 }
 
 // Not implementing request guard as the different verification logic
 // for uploading and cancelling upload. The second endpoint requires
 // a list of task uuids.
 impl UploadTask {
-    pub fn new(upload_req: &UploadRequest, userid: i64, target_path: PathBuf) -> Self {
+    pub fn new(
+        upload_req: &UploadRequest,
+        userid: i64,
+        share_id: i64,
+        target_path: &Path,
+        conflict: ConflictPolicy,
+    ) -> Self {
         let uuid = uuid::Uuid::new_v4().to_string();
+        let now = util::get_utc_seconds();
 
         Self {
             uuid,
             userid,
+            share_id,
             filename: upload_req.filename.to_owned(),
-            size: upload_req.size,
-            dir: target_path,
+            size: upload_req.size as i64,
+            dir: target_path.to_string_lossy().to_string(),
             hash: upload_req.hash.to_owned(),
             finished_slices: 0,
+            conflict: conflict.as_str().to_owned(),
+            batch_id: upload_req.batch_id.clone().unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
         }
     }
+
+    pub fn target_dir(&self) -> PathBuf {
+        PathBuf::from(&self.dir)
+    }
+
+    pub fn temp_dir(&self) -> PathBuf {
+        util::get_temp_path().join(&self.uuid)
+    }
+
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        ConflictPolicy::from_name(&self.conflict).unwrap_or(ConflictPolicy::Fail)
+    }
+
+    pub async fn insert_query(&self, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<i64> {
+        let sql = "insert into UPLOAD_TASK (uuid, user_id, share_id, filename, size, dir, hash, conflict, batch_id, created_at, updated_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+        let query = Query::new(
+            sql,
+            args![
+                &self.uuid,
+                self.userid,
+                self.share_id,
+                &self.filename,
+                self.size,
+                &self.dir,
+                &self.hash,
+                &self.conflict,
+                &self.batch_id,
+                self.created_at,
+                self.updated_at
+            ],
+        );
+
+        Ok(db::execute(query, tx).await?)
+    }
+
+    pub async fn touch_query(uuid: &str, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "update UPLOAD_TASK set updated_at = ?1 where uuid = ?2";
+        let query = Query::new(sql, args![util::get_utc_seconds(), uuid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    // Counted once for every index, however many times the slice is sent, as the slices
+    // of a task are saved under its upload lock.
+    pub async fn finish_slice_query(uuid: &str, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "update UPLOAD_TASK set finished_slices = finished_slices + 1, updated_at = ?1 where uuid = ?2";
+        let query = Query::new(sql, args![util::get_utc_seconds(), uuid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(uuid: &str, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from UPLOAD_TASK where uuid = ?1";
+        let query = Query::new(sql, args![uuid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_user_tasks_query(
+        userid: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "delete from UPLOAD_TASK where user_id = ?1";
+        let query = Query::new(sql, args![userid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_all_query(tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from UPLOAD_TASK";
+        let query = Query::new(sql, vec![]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_uuid(
+        uuid: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Option<Self>> {
+        let sql = "select * from UPLOAD_TASK where uuid = ?1";
+        let query = Query::new(sql, args![uuid]);
+
+        Ok(db::fetch_single(query, conn).await?)
+    }
+
+    pub async fn find_user_tasks(
+        userid: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from UPLOAD_TASK where user_id = ?1";
+        let query = Query::new(sql, args![userid]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn find_batch_tasks(
+        userid: i64,
+        batch_id: &str,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from UPLOAD_TASK where user_id = ?1 and batch_id = ?2 and share_id = 0";
+        let query = Query::new(sql, args![userid, batch_id]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn find_all(conn: &mut PoolConnection<Sqlite>) -> AnyResult<Vec<Self>> {
+        let sql = "select * from UPLOAD_TASK";
+        let query = Query::new(sql, vec![]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
+    pub async fn find_stale(
+        before: i64,
+        conn: &mut PoolConnection<Sqlite>,
+    ) -> AnyResult<Vec<Self>> {
+        let sql = "select * from UPLOAD_TASK where updated_at < ?1";
+        let query = Query::new(sql, args![before]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbered_filename() {
+        assert_eq!(numbered_filename("a.txt", 1), "a (1).txt");
+        assert_eq!(numbered_filename("a.tar.gz", 2), "a.tar (2).gz");
+        assert_eq!(numbered_filename("README", 1), "README (1)");
+        assert_eq!(numbered_filename(".bashrc", 1), ".bashrc (1)");
+    }
 }
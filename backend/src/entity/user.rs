@@ -1,4 +1,4 @@
-use super::request::SetupRequest;
+use super::request::{CreateUserRequest, SetupRequest};
 use crate::{
     args,
     service::token::{AccessToken, RefreshToken},
@@ -9,13 +9,21 @@ use anyhow::Result as AnyResult;
 use bcrypt::{hash, verify, DEFAULT_COST};
 use sqlx::{pool::PoolConnection, FromRow, Sqlite, Transaction};
 
-#[derive(FromRow)]
+#[derive(FromRow, Default)]
 pub struct User {
     pub user_id: i64,
     pub username: String,
     pub password: String,
     pub permission: i8,
     pub created_at: i64,
+    pub disabled: i8,
+    pub totp_secret: String,
+    pub totp_enabled: i8,
+    pub totp_last_step: i64,
+    // Relative to the storage, or empty to browse the whole storage.
+    pub home_dir: String,
+    // Max bytes of the files uploaded by the user, or 0 for no limit.
+    pub quota_bytes: i64,
 }
 
 impl User {
@@ -26,6 +34,18 @@ impl User {
             password: req.password.to_string(),
             permission: 9,
             created_at,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_create_req(req: &CreateUserRequest, created_at: i64) -> Self {
+        Self {
+            user_id: 0,
+            username: req.username.to_string(),
+            password: req.password.to_string(),
+            permission: req.permission,
+            created_at,
+            ..Default::default()
         }
     }
 
@@ -36,6 +56,7 @@ impl User {
             password: String::new(),
             permission: 0,
             created_at,
+            ..Default::default()
         }
     }
 
@@ -80,6 +101,137 @@ impl User {
         Ok(uid)
     }
 
+    // Update the permission only, as `update()` hashes the password field again.
+    pub async fn update_permission_query(
+        uid: i64,
+        permission: i8,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set permission = ?1 where user_id = ?2";
+        let query = Query::new(sql, args![permission, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_disabled_query(
+        uid: i64,
+        disabled: bool,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set disabled = ?1 where user_id = ?2";
+        let query = Query::new(sql, args![if disabled { 1 } else { 0 }, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_quota_query(
+        uid: i64,
+        quota_bytes: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set quota_bytes = ?1 where user_id = ?2";
+        let query = Query::new(sql, args![quota_bytes, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_home_dir_query(
+        uid: i64,
+        home_dir: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set home_dir = ?1 where user_id = ?2";
+        let query = Query::new(sql, args![home_dir, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    // Same as `Hidden::update_all_sub_path_query()`, for home directories renamed by admins.
+    pub async fn update_home_sub_path_query(
+        tx: &mut Transaction<'_, Sqlite>,
+        current_path: &str,
+        new_path: &str,
+    ) -> AnyResult<()> {
+        let sql = "update USER set home_dir = ?1 where home_dir = ?2";
+        let query = Query::new(sql, args![new_path, current_path]);
+
+        db::execute(query, tx).await?;
+
+        let sql =
+            "update USER set home_dir = replace(substr(home_dir, ?1, ?2), ?3, ?4) || substr(home_dir, ?5) where home_dir like ?6";
+        let query = Query::new(
+            sql,
+            args![
+                1,
+                current_path.len() + 2,
+                format!("{}/", current_path),
+                format!("{}/", new_path),
+                current_path.len() + 3,
+                format!("{}/%", current_path)
+            ],
+        );
+
+        db::execute(query, tx).await?;
+
+        Ok(())
+    }
+
+    // Save the secret before enabling, so the user can scan it and confirm with a code.
+    pub async fn update_totp_secret_query(
+        uid: i64,
+        secret: &str,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set totp_secret = ?1, totp_enabled = 0, totp_last_step = 0 where user_id = ?2";
+        let query = Query::new(sql, args![secret, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_totp_enabled_query(
+        uid: i64,
+        enabled: bool,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set totp_enabled = ?1 where user_id = ?2";
+        let query = Query::new(sql, args![if enabled { 1 } else { 0 }, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn update_totp_last_step_query(
+        uid: i64,
+        step: i64,
+        tx: &mut Transaction<'_, Sqlite>,
+    ) -> AnyResult<()> {
+        let sql = "update USER set totp_last_step = ?1 where user_id = ?2";
+        let query = Query::new(sql, args![step, uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn delete_query(uid: i64, tx: &mut Transaction<'_, Sqlite>) -> AnyResult<()> {
+        let sql = "delete from USER where user_id = ?1";
+        let query = Query::new(sql, args![uid]);
+
+        db::execute(query, tx).await?;
+        Ok(())
+    }
+
+    pub async fn find_all(conn: &mut PoolConnection<Sqlite>) -> AnyResult<Vec<Self>> {
+        let sql = "select * from USER order by user_id";
+        let query = Query::new(sql, vec![]);
+
+        Ok(db::fetch_multiple(query, conn).await?)
+    }
+
     pub async fn find_user_by_name(
         username: &str,
         conn: &mut PoolConnection<Sqlite>,
@@ -114,14 +266,26 @@ impl User {
             return Err(anyhow::anyhow!("Invalid password to login"));
         }
 
+        if user.is_disabled() {
+            return Err(anyhow::anyhow!("User account disabled"));
+        }
+
         Ok(user)
     }
 
+    pub fn is_disabled(&self) -> bool {
+        self.disabled > 0
+    }
+
+    pub fn is_totp_enabled(&self) -> bool {
+        self.totp_enabled > 0 && !self.totp_secret.is_empty()
+    }
+
     pub fn generate_access_token(&self) -> AccessToken {
         AccessToken::new(self.user_id, self.permission)
     }
 
-    pub fn generate_refresh_token(&self) -> RefreshToken {
-        RefreshToken::new(self.user_id)
+    pub fn generate_refresh_token(&self, session_id: &str) -> RefreshToken {
+        RefreshToken::new(self.user_id, session_id)
     }
 }
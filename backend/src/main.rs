@@ -5,19 +5,13 @@ mod entity;
 mod service;
 mod util;
 use crate::util::local_ip::ServerConfig;
-use entity::{copy_move_task::CopyMoveTask, site::Site};
-use lazy_static::lazy_static;
+use entity::site::Site;
 use rocket::fs::FileServer;
 use service::app_state::AppState;
-use service::fairings::StaticFileCache;
-use std::sync::Mutex;
-use std::{sync::Arc, thread, time};
+use service::fairings::{AuditLogger, StaticFileCache};
+use std::{thread, time};
 use util::{init, local_ip, rocket_env::RocketEnv};
 
-lazy_static! {
-    static ref COPY_MOVE_TASK: Arc<Mutex<Option<CopyMoveTask>>> = Arc::new(Mutex::new(None));
-}
-
 #[tokio::main]
 async fn main() {
     if let Err(e) = launch().await {
@@ -40,13 +34,15 @@ async fn launch() -> Result<(), anyhow::Error> {
     init::check_update(&mut conn).await?;
 
     let site_op = Site::read(&mut conn).await?;
-    let state = AppState::new(site_op, pool);
     let config = ServerConfig::new()?;
     RocketEnv::setup(&config);
+    service::upload_gc::spawn(pool.clone(), config.upload_expire_hours * 60 * 60);
+    let state = AppState::new(site_op, pool, config.job_concurrency);
 
     let rocket = rocket::build()
         .manage(state)
         .attach(StaticFileCache)
+        .attach(AuditLogger)
         .mount("/api", api::serve())
         .mount("/", service::static_route::serve())
         .mount("/", FileServer::from(util::get_frontend_path()))
@@ -0,0 +1,226 @@
+use super::{app_state::AppState, auth::AuthUser};
+use crate::entity::{
+    acl::{Access, Acl, AclSubject},
+    group::Group,
+    hidden::Hidden,
+    user::User,
+};
+use crate::util;
+use anyhow::Result as AnyResult;
+use std::path::{Component, Path, PathBuf};
+
+// Resolve what a user can do on the paths under the storage.
+// The nearest path with ACL entries for the user or any of the user's groups decides,
+// so an entry on a sub folder overrides the one on its parent. Without any entry,
+// reading and sharing follow the hidden settings of the parents, while writing and
+// deleting are for admins only.
+// Users with a home directory are confined to it, and see it as the root.
+pub struct AccessControl {
+    storage: PathBuf,
+    root: PathBuf,
+    user: AuthUser,
+    groups: Vec<i64>,
+    hiddens: Vec<Hidden>,
+    acls: Vec<Acl>,
+}
+
+impl AccessControl {
+    pub async fn load(state: &AppState, user: &AuthUser) -> AnyResult<Self> {
+        let storage = PathBuf::from(&state.get_site()?.storage);
+        let mut conn = state.get_pool_conn().await?;
+        let hiddens = Hidden::find_all(&mut conn).await?;
+        let mut root = storage.clone();
+        let mut groups = vec![];
+        let mut acls = vec![];
+
+        if user.uid > 0 {
+            if let Some(u) = User::find_user_by_id(user.uid, &mut conn).await? {
+                if !u.home_dir.is_empty() {
+                    root = resolve_under(&storage, Path::new(&u.home_dir))?;
+                }
+            }
+
+            for group in Group::find_user_groups(user.uid, &mut conn).await? {
+                groups.push(group.group_id);
+            }
+
+            acls = Acl::find_by_subject(AclSubject::User, user.uid, &mut conn).await?;
+            for group_id in groups.iter() {
+                acls.append(
+                    &mut Acl::find_by_subject(AclSubject::Group, *group_id, &mut conn).await?,
+                );
+            }
+        }
+
+        Ok(Self {
+            storage,
+            root,
+            user: user.clone(),
+            groups,
+            hiddens,
+            acls,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Resolve an encoded path from the frontend under the root.
+    pub fn resolve(&self, path: &str) -> AnyResult<PathBuf> {
+        resolve_under(&self.root, &util::parse_encoded_url(path)?)
+    }
+
+    // HIDDEN and ACL records are saved with paths relative to the storage.
+    pub fn storage_relative(&self, full_path: &Path) -> AnyResult<String> {
+        let relative = full_path.strip_prefix(&self.storage)?;
+        let relative_str = relative
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid path: {:?}", relative))?;
+
+        Ok(relative_str.to_owned())
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.user.uid > 0 && self.user.permission == 9
+    }
+
+    pub fn can(&self, path: &Path, access: Access) -> bool {
+        if self.user.read_only && access != Access::Read {
+            return false;
+        }
+
+        if self.is_admin() {
+            return true;
+        }
+
+        if let Some(allowed) = self.acl_decision(path, access) {
+            return allowed;
+        }
+
+        match access {
+            Access::Read | Access::Share => self.is_visible(path),
+            Access::Write | Access::Delete => false,
+        }
+    }
+
+    fn acl_decision(&self, path: &Path, access: Access) -> Option<bool> {
+        let mut current = Some(path);
+
+        while let Some(p) = current {
+            let mut entries = self
+                .acls
+                .iter()
+                .filter(|acl| self.storage.join(&acl.path) == p)
+                .peekable();
+
+            if entries.peek().is_some() {
+                return Some(entries.any(|acl| acl.allows(access)));
+            }
+
+            if p == self.storage {
+                break;
+            }
+            current = p.parent();
+        }
+
+        None
+    }
+
+    // Check the hidden settings of all the parents of the input file path.
+    fn is_visible(&self, path: &Path) -> bool {
+        self.hiddens.iter().all(|hidden| {
+            !path.starts_with(self.storage.join(&hidden.path))
+                || hidden.least_permission <= self.user.permission
+                || (hidden.group_id > 0 && self.groups.contains(&hidden.group_id))
+        })
+    }
+}
+
+// Join the relative path to the root, rejecting any component that leaves the root.
+pub fn resolve_under(root: &Path, path: &Path) -> AnyResult<PathBuf> {
+    let mut target = root.to_path_buf();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => target.push(c),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                return Err(anyhow::anyhow!("Path out of root: {:?}", path));
+            }
+        }
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl(path: &str, subject: AclSubject, read: i8, write: i8) -> Acl {
+        Acl {
+            acl_id: 0,
+            path: path.to_owned(),
+            subject_type: subject.as_str().to_owned(),
+            subject_id: 2,
+            can_read: read,
+            can_write: write,
+            can_delete: 0,
+            can_share: 0,
+        }
+    }
+
+    fn access_control(permission: i8, acls: Vec<Acl>) -> AccessControl {
+        AccessControl {
+            storage: PathBuf::from("/storage"),
+            root: PathBuf::from("/storage"),
+            user: AuthUser {
+                uid: 2,
+                permission,
+                read_only: false,
+            },
+            groups: vec![2],
+            hiddens: vec![Hidden::new("secret", 9, 0), Hidden::new("media", 9, 2)],
+            acls,
+        }
+    }
+
+    #[test]
+    fn test_default_access() {
+        let access = access_control(1, vec![]);
+        assert!(access.can(Path::new("/storage/docs/a.txt"), Access::Read));
+        assert!(!access.can(Path::new("/storage/secret/a.txt"), Access::Read));
+        assert!(access.can(Path::new("/storage/media/a.mp4"), Access::Read));
+        assert!(!access.can(Path::new("/storage/docs"), Access::Write));
+        assert!(access_control(9, vec![]).can(Path::new("/storage/secret"), Access::Delete));
+    }
+
+    #[test]
+    fn test_nearest_entry_wins() {
+        let access = access_control(
+            1,
+            vec![
+                acl("projects", AclSubject::User, 1, 1),
+                acl("projects/archive", AclSubject::Group, 1, 0),
+                acl("secret", AclSubject::Group, 1, 0),
+            ],
+        );
+        assert!(access.can(Path::new("/storage/projects/a/b.txt"), Access::Write));
+        assert!(!access.can(Path::new("/storage/projects/archive/b.txt"), Access::Write));
+        assert!(!access.can(Path::new("/storage/projects"), Access::Delete));
+        assert!(access.can(Path::new("/storage/secret/a.txt"), Access::Read));
+        assert!(!access.can(Path::new("/storage/secret/a.txt"), Access::Share));
+    }
+
+    #[test]
+    fn test_resolve_under() {
+        let root = Path::new("/storage/home/bob");
+        assert_eq!(
+            resolve_under(root, Path::new("/docs/./a.txt")).unwrap(),
+            PathBuf::from("/storage/home/bob/docs/a.txt")
+        );
+        assert_eq!(resolve_under(root, Path::new("")).unwrap(), root);
+        assert!(resolve_under(root, Path::new("docs/../../alice")).is_err());
+    }
+}
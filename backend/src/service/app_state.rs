@@ -1,4 +1,7 @@
-use crate::entity::{site::Site, upload_task::UploadTask};
+use crate::entity::site::Site;
+use crate::service::fetch::FetchManager;
+use crate::service::job::JobManager;
+use crate::service::upload_lock::UploadLocks;
 use anyhow::Result as AnyResult;
 use sqlx::{pool::PoolConnection, Pool, Sqlite};
 use std::sync::{atomic::AtomicBool, atomic::Ordering, Arc, Mutex, MutexGuard};
@@ -8,11 +11,13 @@ pub struct AppState {
     pub first_run: AtomicBool,
     pub site: Arc<Mutex<Site>>,
     pub pool: Pool<Sqlite>,
-    pub uploads: Arc<Mutex<Vec<UploadTask>>>,
+    pub jobs: JobManager,
+    pub fetches: FetchManager,
+    pub upload_locks: UploadLocks,
 }
 
 impl AppState {
-    pub fn new(site_op: Option<Site>, pool: Pool<Sqlite>) -> Self {
+    pub fn new(site_op: Option<Site>, pool: Pool<Sqlite>, job_concurrency: usize) -> Self {
         let first_run = site_op.is_none();
         let site = match site_op {
             Some(site) => site,
@@ -23,7 +28,9 @@ impl AppState {
             first_run: AtomicBool::new(first_run),
             site: Arc::new(Mutex::new(site)),
             pool,
-            uploads: Arc::new(Mutex::new(vec![])),
+            jobs: JobManager::new(job_concurrency),
+            fetches: FetchManager::default(),
+            upload_locks: UploadLocks::default(),
         }
     }
 
@@ -60,36 +67,4 @@ impl AppState {
 
         Ok(())
     }
-
-    pub fn get_upload_tasks(&self) -> AnyResult<MutexGuard<Vec<UploadTask>>> {
-        match self.uploads.lock() {
-            Ok(v) => Ok(v),
-            Err(e) => return Err(anyhow::anyhow!("Cannot retrieve site from state: {}", e)),
-        }
-    }
-
-    pub fn find_upload_uuid(&self, uuid: &str) -> AnyResult<Option<UploadTask>> {
-        let uploads = self.get_upload_tasks()?;
-        for task in uploads.iter() {
-            if task.uuid == uuid {
-                return Ok(Some(task.to_owned()));
-            }
-        }
-
-        Ok(None)
-    }
-
-    pub fn push_upload_task(&self, target_task: UploadTask) -> AnyResult<()> {
-        let mut uploads = self.get_upload_tasks()?;
-        (*uploads).push(target_task);
-
-        Ok(())
-    }
-
-    pub fn remove_upload_task(&self, target_task: UploadTask) -> AnyResult<()> {
-        let mut uploads = self.get_upload_tasks()?;
-        (*uploads).retain(|upload| upload.uuid != target_task.uuid);
-
-        Ok(())
-    }
 }
@@ -0,0 +1,65 @@
+use crate::entity::audit::AuditAction;
+use crate::entity::error::Error;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub user_id: i64,
+    pub action: AuditAction,
+    pub path: String,
+    pub target: String,
+}
+
+// Cached in the request, and saved by the `AuditLogger` fairing with the response status.
+#[derive(Debug, Default)]
+pub struct AuditRecord {
+    entry: Mutex<Option<AuditEntry>>,
+}
+
+impl AuditRecord {
+    pub fn take(&self) -> Option<AuditEntry> {
+        self.entry.lock().ok()?.take()
+    }
+}
+
+pub struct Auditor<'r> {
+    record: &'r AuditRecord,
+}
+
+impl<'r> Auditor<'r> {
+    // Call it before any early return so failures are recorded as well.
+    // Calling it again replaces the entry, e.g. once the user of a login is known.
+    // The path is URL encoded as in the request, and saved decoded.
+    pub fn log(&self, user_id: i64, action: AuditAction, path: &str, target: &str) {
+        if let Ok(mut entry) = self.record.entry.lock() {
+            *entry = Some(AuditEntry {
+                user_id,
+                action,
+                path: decode_path(path),
+                target: target.to_owned(),
+            });
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Auditor<'r> {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let record = req.local_cache(AuditRecord::default);
+
+        Outcome::Success(Auditor { record })
+    }
+}
+
+pub fn decode_path(path: &str) -> String {
+    match urlencoding::decode(path) {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => path.to_owned(),
+    }
+}
@@ -1,16 +1,24 @@
-use super::{app_state::AppState, token::AccessToken, token::Token};
+use super::{
+    app_state::AppState,
+    token::{AccessToken, Token, TwoFactorToken},
+};
+use crate::entity::api_token::ApiToken;
 use crate::entity::error::Error;
 use crate::util::constants::ACCESS_TOKEN;
 use rocket::{
-    http::Status,
+    http::{Method, Status},
     request::{FromRequest, Outcome},
     Request,
 };
+use sqlx::Connection;
 
 #[derive(Default, Clone)]
 pub struct AuthUser {
     pub uid: i64,
     pub permission: i8,
+    // Set when authenticated by an API token with read scope, which is only let through
+    // to GET and HEAD requests.
+    pub read_only: bool,
 }
 
 #[derive(Default, Clone)]
@@ -18,30 +26,39 @@ pub struct AuthAdmin {
     pub uid: i64,
 }
 
+// A signed in user, or a user who is in the middle of a two factor login,
+// which is allowed to enrol when the site requires it.
+#[derive(Default, Clone)]
+pub struct TwoFactorUser {
+    pub uid: i64,
+}
+
+impl From<AuthAdmin> for AuthUser {
+    fn from(admin: AuthAdmin) -> Self {
+        AuthUser {
+            uid: admin.uid,
+            permission: 9,
+            read_only: false,
+        }
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AuthUser {
     type Error = Error;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        if let Some(token_str) = req.cookies().get(ACCESS_TOKEN) {
-            if let Some(state) = req.rocket().state::<AppState>() {
-                if let Ok(secret) = state.get_secret() {
-                    if let Ok(token) = AccessToken::decode(token_str.value(), &secret) {
-                        if let Ok(allow_guest) = state.get_allow_guest() {
-                            if token.uid > (0 - allow_guest) as i64
-                                && token.permission > 0 - allow_guest
-                            {
-                                return Outcome::Success(AuthUser {
-                                    uid: token.uid,
-                                    permission: token.permission,
-                                });
-                            } else {
-                                return Outcome::Failure((
-                                    Status::Unauthorized,
-                                    Error::Unauthorized,
-                                ));
-                            }
-                        }
+        if let Some(state) = req.rocket().state::<AppState>() {
+            if let Some(user) = authenticate(req, state).await {
+                if let Ok(allow_guest) = state.get_allow_guest() {
+                    if user.read_only && !matches!(req.method(), Method::Get | Method::Head) {
+                        return Outcome::Failure((Status::Forbidden, Error::Forbidden));
+                    } else if user.uid > (0 - allow_guest) as i64
+                        && user.permission > 0 - allow_guest
+                    {
+                        return Outcome::Success(user);
+                    } else {
+                        return Outcome::Failure((Status::Unauthorized, Error::Unauthorized));
                     }
                 }
             }
@@ -56,20 +73,71 @@ impl<'r> FromRequest<'r> for AuthAdmin {
     type Error = Error;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        if let Some(token_str) = req.cookies().get(ACCESS_TOKEN) {
-            if let Some(state) = req.rocket().state::<AppState>() {
-                if let Ok(secret) = state.get_secret() {
-                    if let Ok(token) = AccessToken::decode(token_str.value(), &secret) {
-                        if token.uid > 0 && token.permission == 9 {
-                            return Outcome::Success(AuthAdmin { uid: token.uid });
-                        } else {
-                            return Outcome::Failure((Status::Unauthorized, Error::Unauthorized));
-                        }
-                    }
+        if let Some(state) = req.rocket().state::<AppState>() {
+            if let Some(user) = authenticate(req, state).await {
+                if user.uid > 0 && user.permission == 9 && !user.read_only {
+                    return Outcome::Success(AuthAdmin { uid: user.uid });
+                } else {
+                    return Outcome::Failure((Status::Unauthorized, Error::Unauthorized));
+                }
+            }
+        }
+
+        Outcome::Failure((Status::Unauthorized, Error::Unauthorized))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TwoFactorUser {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(state) = req.rocket().state::<AppState>() {
+            if let Some(user) = authenticate(req, state).await {
+                if user.uid > 0 && !user.read_only {
+                    return Outcome::Success(TwoFactorUser { uid: user.uid });
                 }
             }
         }
 
+        if let Outcome::Success(token) = req.guard::<TwoFactorToken>().await {
+            return Outcome::Success(TwoFactorUser { uid: token.uid });
+        }
+
         Outcome::Failure((Status::Unauthorized, Error::Unauthorized))
     }
 }
+
+// Read the access token cookie first, then fall back to the `Authorization: Bearer` header.
+async fn authenticate(req: &Request<'_>, state: &AppState) -> Option<AuthUser> {
+    let secret = state.get_secret().ok()?;
+
+    if let Some(token_str) = req.cookies().get(ACCESS_TOKEN) {
+        if let Ok(token) = AccessToken::decode(token_str.value(), &secret) {
+            return Some(AuthUser {
+                uid: token.uid,
+                permission: token.permission,
+                read_only: false,
+            });
+        }
+    }
+
+    let header = req.headers().get_one("Authorization")?;
+    let plain = header.strip_prefix("Bearer ")?.trim();
+    let mut conn = state.get_pool_conn().await.ok()?;
+    let owner = ApiToken::find_owner(plain, &secret, &mut conn)
+        .await
+        .ok()??;
+
+    let mut tx = conn.begin().await.ok()?;
+    ApiToken::update_last_used_query(owner.token_id, &mut tx)
+        .await
+        .ok()?;
+    tx.commit().await.ok()?;
+
+    Some(AuthUser {
+        uid: owner.user_id,
+        permission: owner.permission,
+        read_only: !owner.is_writable(),
+    })
+}
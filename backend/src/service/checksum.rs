@@ -0,0 +1,39 @@
+use crate::entity::error::Error;
+use crate::util::hash;
+use rocket::http::Status;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+// The optional checksum of a request body, in the format of the `Upload-Checksum` header of tus.
+pub struct UploadChecksum {
+    pub checksum: Option<(String, Vec<u8>)>,
+}
+
+impl UploadChecksum {
+    // Return `None` if the algorithm is not supported, and true if there is no checksum.
+    pub fn verify(&self, data: &[u8]) -> Option<bool> {
+        match &self.checksum {
+            Some((algorithm, expected)) => hash::verify_checksum(algorithm, expected, data),
+            None => Some(true),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UploadChecksum {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("Upload-Checksum") {
+            Some(v) => match hash::parse_checksum(v) {
+                Some(checksum) => Outcome::Success(UploadChecksum {
+                    checksum: Some(checksum),
+                }),
+                None => Outcome::Failure((Status::BadRequest, Error::BadRequest)),
+            },
+            None => Outcome::Success(UploadChecksum { checksum: None }),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::entity::error::Error;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+// Information about the remote client, used to track sessions.
+#[derive(Debug, Default, Clone)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ip = match req.client_ip() {
+            Some(ip) => ip.to_string(),
+            None => String::new(),
+        };
+
+        let user_agent = req.headers().get_one("User-Agent").unwrap_or("").to_owned();
+
+        Outcome::Success(ClientInfo { ip, user_agent })
+    }
+}
@@ -0,0 +1,28 @@
+use crate::entity::error::Error;
+use rocket::http::Status;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+// The length of the request body, absent for a chunked request.
+pub struct ContentLength {
+    pub length: Option<u64>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentLength {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.headers().get_one("Content-Length") {
+            Some(v) => match v.trim().parse() {
+                Ok(length) => Outcome::Success(ContentLength {
+                    length: Some(length),
+                }),
+                Err(_) => Outcome::Failure((Status::BadRequest, Error::BadRequest)),
+            },
+            None => Outcome::Success(ContentLength { length: None }),
+        }
+    }
+}
@@ -1,11 +1,16 @@
+use super::{app_state::AppState, audit::AuditRecord};
+use crate::entity::audit::Audit;
 use crate::util::constants;
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::uri::Path;
 use rocket::http::{Method, Status};
 use rocket::{Request, Response};
+use sqlx::Connection;
 
 pub struct StaticFileCache;
 
+pub struct AuditLogger;
+
 #[rocket::async_trait]
 impl Fairing for StaticFileCache {
     fn info(&self) -> Info {
@@ -29,6 +34,51 @@ impl Fairing for StaticFileCache {
     }
 }
 
+#[rocket::async_trait]
+impl Fairing for AuditLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Audit logger",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let entry = match request.local_cache(AuditRecord::default).take() {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let ip = match request.client_ip() {
+            Some(ip) => ip.to_string(),
+            None => String::new(),
+        };
+        let audit = Audit::new(
+            entry.user_id,
+            &ip,
+            entry.action,
+            &entry.path,
+            &entry.target,
+            response.status().code,
+        );
+
+        if let Some(state) = request.rocket().state::<AppState>() {
+            if let Err(e) = save_audit(state, &audit).await {
+                eprintln!("Error saving audit record: {}", e);
+            }
+        }
+    }
+}
+
+async fn save_audit(state: &AppState, audit: &Audit) -> anyhow::Result<()> {
+    let mut conn = state.get_pool_conn().await?;
+    let mut tx = conn.begin().await?;
+    audit.insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
 // Do not cache development related files in debug mode.
 #[cfg(debug_assertions)]
 fn req_static<'r>(req_path: &Path) -> bool {
@@ -0,0 +1,414 @@
+use crate::entity::fetch_task::{FetchTask, FetchTaskStatus};
+use crate::entity::quota::UploadedFile;
+use crate::service::quota;
+use crate::util::{
+    self,
+    constants::{
+        FETCH_CONNECT_TIMEOUT_SECS, FETCH_HISTORY_LIMIT, FETCH_MAX_RETRIES,
+        FETCH_READ_TIMEOUT_SECS, FETCH_RETRY_DELAY_SECS,
+    },
+};
+use anyhow::Result as AnyResult;
+use reqwest::{header, Client, StatusCode};
+use sqlx::{Connection, Pool, Sqlite};
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Notify;
+
+type TaskMap = Arc<Mutex<HashMap<String, FetchTask>>>;
+type CancelMap = Arc<Mutex<HashMap<String, Arc<Notify>>>>;
+
+// Holds the running fetch tasks, and the latest finished ones for their status to be seen.
+// A running task is woken up when cancelled, even while waiting for the server.
+#[derive(Debug, Default)]
+pub struct FetchManager {
+    tasks: TaskMap,
+    cancels: CancelMap,
+}
+
+impl FetchManager {
+    pub fn get(&self, uuid: &str) -> Option<FetchTask> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.get(uuid).cloned()
+    }
+
+    pub fn get_all(&self) -> Vec<FetchTask> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.values().cloned().collect()
+    }
+
+    // Return false if the task is not found or already finished.
+    pub fn cancel(&self, uuid: &str) -> bool {
+        let mut tasks = self.tasks.lock().unwrap();
+        match tasks.get_mut(uuid) {
+            Some(task) if !task.is_finished() => {
+                task.status = FetchTaskStatus::Cancelled;
+                task.finished_at = util::get_utc_seconds();
+                if let Some(cancel) = self.cancels.lock().unwrap().get(uuid) {
+                    cancel.notify_one();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // The url is downloaded to a part file in the target dir, which is moved into place
+    // according to the conflict policy of the task once complete.
+    pub fn submit(&self, pool: Pool<Sqlite>, storage: String, task: FetchTask) {
+        self.tasks
+            .lock()
+            .unwrap()
+            .insert(task.uuid.clone(), task.clone());
+        let cancel = Arc::new(Notify::new());
+        self.cancels
+            .lock()
+            .unwrap()
+            .insert(task.uuid.clone(), cancel.clone());
+
+        let tasks = self.tasks.clone();
+        let cancels = self.cancels.clone();
+        tokio::spawn(async move {
+            let part_file = task.target.join(format!(".{}.part", task.uuid));
+            let result = run(&tasks, &pool, &storage, &task, &part_file, &cancel).await;
+            cancels.lock().unwrap().remove(&task.uuid);
+            if part_file.exists() {
+                if let Err(e) = fs::remove_file(&part_file).await {
+                    eprintln!("Error removing {:?}: {}", part_file, e);
+                }
+            }
+
+            match result {
+                Ok(_) => finish(&tasks, &task.uuid, FetchTaskStatus::Success, ""),
+                Err(e) => {
+                    eprintln!("Fetch task {} failed: {}", task.uuid, e);
+                    finish(&tasks, &task.uuid, FetchTaskStatus::Failed, &e.to_string());
+                }
+            }
+        });
+    }
+}
+
+async fn run(
+    tasks: &TaskMap,
+    pool: &Pool<Sqlite>,
+    storage: &str,
+    task: &FetchTask,
+    part_file: &PathBuf,
+    cancel: &Notify,
+) -> AnyResult<()> {
+    let client = Client::builder()
+        .connect_timeout(Duration::from_secs(FETCH_CONNECT_TIMEOUT_SECS))
+        .build()?;
+    let size = download(
+        &client,
+        &task.url,
+        part_file,
+        task.max_size,
+        cancel,
+        |downloaded, total| update_progress(tasks, &task.uuid, downloaded, total),
+    )
+    .await?;
+
+    let mut conn = pool.acquire().await?;
+    if quota::exceeds_quota(
+        Path::new(storage),
+        task.user_id,
+        &task.target,
+        size,
+        None,
+        &mut conn,
+    )
+    .await?
+    {
+        return Err(anyhow::anyhow!("Quota exceeded"));
+    }
+
+    let saved_file = match task.conflict.resolve(&task.target, &task.filename)? {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    fs::rename(part_file, &saved_file).await?;
+
+    let relative_path = saved_file.strip_prefix(storage)?.to_string_lossy();
+    let mut tx = conn.begin().await?;
+    UploadedFile::new(&relative_path, task.user_id, size as i64)
+        .upsert_query(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+fn update_progress(tasks: &TaskMap, uuid: &str, downloaded: u64, total: u64) {
+    let mut tasks = tasks.lock().unwrap();
+    if let Some(task) = tasks.get_mut(uuid) {
+        if task.status != FetchTaskStatus::Cancelled {
+            task.status = FetchTaskStatus::InProgress;
+            task.downloaded = downloaded;
+            task.total = total;
+            if total > 0 {
+                task.progress = downloaded as f64 / total as f64;
+            }
+        }
+    }
+}
+
+// A cancelled task is never changed to another status. Only the latest finished tasks
+// are kept.
+fn finish(tasks: &TaskMap, uuid: &str, status: FetchTaskStatus, error: &str) {
+    let mut tasks = tasks.lock().unwrap();
+    if let Some(task) = tasks.get_mut(uuid) {
+        if task.status != FetchTaskStatus::Cancelled {
+            task.status = status;
+            task.error = error.to_owned();
+            task.finished_at = util::get_utc_seconds();
+            if status == FetchTaskStatus::Success {
+                task.progress = 1.0;
+            }
+        }
+    }
+
+    let mut finished: Vec<(i64, String)> = tasks
+        .values()
+        .filter(|t| t.is_finished())
+        .map(|t| (t.finished_at, t.uuid.clone()))
+        .collect();
+    if finished.len() > FETCH_HISTORY_LIMIT {
+        finished.sort();
+        for (_, uuid) in &finished[..finished.len() - FETCH_HISTORY_LIMIT] {
+            tasks.remove(uuid);
+        }
+    }
+}
+
+// A broken or stalled connection is retried with a range request from the bytes already
+// written, and a server ignoring the range sends the file again from the start. The
+// callback is given the bytes downloaded and the total, which is zero if unknown, and
+// the download stops as soon as the cancel is notified. Return the size of the file.
+pub async fn download<F: FnMut(u64, u64)>(
+    client: &Client,
+    url: &str,
+    part_file: &Path,
+    max_size: u64,
+    cancel: &Notify,
+    mut on_progress: F,
+) -> AnyResult<u64> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(part_file)
+        .await?;
+    let mut downloaded = 0;
+    let mut retries = 0;
+
+    while !download_from(
+        client,
+        url,
+        &mut file,
+        &mut downloaded,
+        max_size,
+        cancel,
+        &mut on_progress,
+    )
+    .await?
+    {
+        retries += 1;
+        if retries > FETCH_MAX_RETRIES {
+            return Err(anyhow::anyhow!(
+                "Connection broken after {} retries",
+                retries - 1
+            ));
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(FETCH_RETRY_DELAY_SECS)) => {}
+            _ = cancel.notified() => return Err(anyhow::anyhow!("Cancelled")),
+        }
+    }
+    file.sync_all().await?;
+
+    Ok(downloaded)
+}
+
+// Return false if the connection is broken before the end of the file.
+async fn download_from<F: FnMut(u64, u64)>(
+    client: &Client,
+    url: &str,
+    file: &mut File,
+    downloaded: &mut u64,
+    max_size: u64,
+    cancel: &Notify,
+    on_progress: &mut F,
+) -> AnyResult<bool> {
+    let mut request = client.get(url);
+    if *downloaded > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", downloaded));
+    }
+
+    let sent = tokio::select! {
+        sent = request.send() => sent,
+        _ = cancel.notified() => return Err(anyhow::anyhow!("Cancelled")),
+    };
+    let mut response = match sent {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("Error requesting {}: {}", url, e);
+            return Ok(false);
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow::anyhow!("Unexpected response status: {}", status));
+    }
+
+    if *downloaded > 0 && status != StatusCode::PARTIAL_CONTENT {
+        *downloaded = 0;
+        file.set_len(0).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+    }
+
+    let total = match response.content_length() {
+        Some(length) => length + *downloaded,
+        None => 0,
+    };
+    if max_size > 0 && total > max_size {
+        return Err(anyhow::anyhow!("File larger than {} bytes", max_size));
+    }
+
+    loop {
+        let read_timeout = Duration::from_secs(FETCH_READ_TIMEOUT_SECS);
+        let read = tokio::select! {
+            read = tokio::time::timeout(read_timeout, response.chunk()) => read,
+            _ = cancel.notified() => return Err(anyhow::anyhow!("Cancelled")),
+        };
+        let chunk = match read {
+            Ok(Ok(Some(chunk))) => chunk,
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) => {
+                eprintln!("Error downloading {}: {}", url, e);
+                return Ok(false);
+            }
+            Err(_) => {
+                eprintln!("Timed out downloading {}", url);
+                return Ok(false);
+            }
+        };
+
+        *downloaded += chunk.len() as u64;
+        if max_size > 0 && *downloaded > max_size {
+            return Err(anyhow::anyhow!("File larger than {} bytes", max_size));
+        }
+
+        file.write_all(&chunk).await?;
+        on_progress(*downloaded, total);
+    }
+
+    Ok(total == 0 || *downloaded >= total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    // Serve the data over http, dropping the first connection halfway through the body.
+    async fn serve(data: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut first = true;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let n = socket.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..n]).to_lowercase();
+                let start: usize = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.trim_end_matches('-').parse().ok())
+                    .unwrap_or(0);
+
+                let header = if start > 0 {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                        start,
+                        data.len() - 1,
+                        data.len(),
+                        data.len() - start
+                    )
+                } else {
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", data.len())
+                };
+                socket.write_all(header.as_bytes()).await.unwrap();
+
+                let end = if first { data.len() / 2 } else { data.len() };
+                socket.write_all(&data[start..end]).await.unwrap();
+                first = false;
+            }
+        });
+
+        format!("http://{}/file.bin", addr)
+    }
+
+    #[tokio::test]
+    async fn test_download_resume() {
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let url = serve(data.clone()).await;
+        let part_file = std::env::temp_dir().join(format!("{}.part", uuid::Uuid::new_v4()));
+
+        let mut last_total = 0;
+        let cancel = Notify::new();
+        let size = download(&Client::new(), &url, &part_file, 0, &cancel, |_, total| {
+            last_total = total
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(last_total, data.len() as u64);
+        assert_eq!(std::fs::read(&part_file).unwrap(), data);
+
+        let too_large = download(&Client::new(), &url, &part_file, 1000, &cancel, |_, _| {}).await;
+        assert!(too_large.is_err());
+        std::fs::remove_file(&part_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_cancel() {
+        // Send the headers and then nothing.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let _ = socket.read(&mut buffer).await.unwrap();
+            let header = "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n";
+            socket.write_all(header.as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(FETCH_READ_TIMEOUT_SECS * 2)).await;
+        });
+
+        let part_file = std::env::temp_dir().join(format!("{}.part", uuid::Uuid::new_v4()));
+        let cancel = Arc::new(Notify::new());
+        let notifier = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            notifier.notify_one();
+        });
+
+        let started = std::time::Instant::now();
+        let result = download(&Client::new(), &url, &part_file, 0, &cancel, |_, _| {}).await;
+        assert_eq!(result.unwrap_err().to_string(), "Cancelled");
+        assert!(started.elapsed() < Duration::from_secs(FETCH_READ_TIMEOUT_SECS));
+        std::fs::remove_file(&part_file).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+use crate::entity::copy_move_task::{CopyMoveTask, CopyMoveTaskStatus};
+use crate::entity::job::Job;
+use crate::util::constants::JOB_PAUSE_POLL_MILLIS;
+use anyhow::Result as AnyResult;
+use rocket::tokio::runtime::Handle;
+use rocket::tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use rocket::tokio::task::{self, JoinHandle};
+use sqlx::{Acquire, Pool, Sqlite};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type TaskMap = Arc<Mutex<HashMap<String, CopyMoveTask>>>;
+type CancelMap = Arc<Mutex<HashMap<String, Arc<Notify>>>>;
+
+// Holds the copy and move tasks of all users, of which at most `concurrency` run at once
+// while the others wait in the queue. A finished task is moved into the job history.
+// The tasks waiting for a slot are woken up when cancelled.
+#[derive(Debug)]
+pub struct JobManager {
+    tasks: TaskMap,
+    cancels: CancelMap,
+    permits: Arc<Semaphore>,
+}
+
+impl JobManager {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            cancels: Arc::new(Mutex::new(HashMap::new())),
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+        }
+    }
+
+    pub fn get(&self, uuid: &str) -> Option<CopyMoveTask> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.get(uuid).cloned()
+    }
+
+    // The oldest first.
+    pub fn find_user_tasks(&self, user_id: i64) -> Vec<CopyMoveTask> {
+        let tasks = self.tasks.lock().unwrap();
+        let mut user_tasks: Vec<CopyMoveTask> = tasks
+            .values()
+            .filter(|t| t.user_id == user_id)
+            .cloned()
+            .collect();
+        user_tasks.sort_by_key(|t| t.created_at);
+
+        user_tasks
+    }
+
+    // Return false if the task is not in one of the states it can change from.
+    pub fn transition(
+        &self,
+        uuid: &str,
+        from: &[CopyMoveTaskStatus],
+        to: CopyMoveTaskStatus,
+    ) -> bool {
+        let mut tasks = self.tasks.lock().unwrap();
+        match tasks.get_mut(uuid) {
+            Some(task) if from.contains(&task.status) => {
+                if to == CopyMoveTaskStatus::Cancelled {
+                    if let Some(cancel) = self.cancels.lock().unwrap().get(uuid) {
+                        cancel.notify_one();
+                    }
+                }
+                task.status = to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // The handle resolves to whether the task succeeded, after it is saved in the history.
+    pub fn submit(
+        &self,
+        task: CopyMoveTask,
+        pool: Pool<Sqlite>,
+        storage: String,
+    ) -> JoinHandle<bool> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .insert(task.uuid.clone(), task.clone());
+        let cancel = Arc::new(Notify::new());
+        self.cancels
+            .lock()
+            .unwrap()
+            .insert(task.uuid.clone(), cancel.clone());
+
+        let tasks = self.tasks.clone();
+        let cancels = self.cancels.clone();
+        let slot = Slot {
+            permits: self.permits.clone(),
+            cancel,
+            permit: None,
+        };
+        rocket::tokio::spawn(async move {
+            let result = run(&tasks, slot, &task).await;
+            cancels.lock().unwrap().remove(&task.uuid);
+            let succeeded = result.is_ok();
+            update(&tasks, &task.uuid, |t| match result {
+                Ok(_) => {
+                    t.status = CopyMoveTaskStatus::Success;
+                    t.progress = 1.0;
+                }
+                Err(e) => {
+                    eprintln!("Error running task {}: {}", t.uuid, e);
+                    if t.status != CopyMoveTaskStatus::Cancelled {
+                        t.status = CopyMoveTaskStatus::Failed;
+                    }
+                    t.error = e.to_string();
+                }
+            });
+
+            // Removed only once saved, so the task can always be found in one or the other.
+            let finished = tasks.lock().unwrap().get(&task.uuid).cloned();
+            if let Some(finished) = finished {
+                if let Err(e) = save_history(&pool, Path::new(&storage), &finished).await {
+                    eprintln!("Error saving job {}: {}", finished.uuid, e);
+                }
+            }
+            tasks.lock().unwrap().remove(&task.uuid);
+
+            succeeded
+        })
+    }
+}
+
+async fn run(tasks: &TaskMap, mut slot: Slot, task: &CopyMoveTask) -> AnyResult<()> {
+    if !slot.acquire().await || status(tasks, &task.uuid) == Some(CopyMoveTaskStatus::Cancelled) {
+        return Err(anyhow::anyhow!("Cancelled before it started"));
+    }
+    update(tasks, &task.uuid, |t| {
+        t.status = CopyMoveTaskStatus::InProgress
+    });
+
+    let tasks = tasks.clone();
+    let task = task.clone();
+    let handle = Handle::current();
+    task::spawn_blocking(move || {
+        task.run(|progress| {
+            update(&tasks, &task.uuid, |t| t.progress = progress);
+            wait_while_paused(&tasks, &task.uuid, &mut slot, &handle)
+        })
+    })
+    .await?
+}
+
+// A paused task gives up its slot to the queue, blocking its thread until it is resumed
+// or cancelled, and then waits for a slot again. Return false if cancelled.
+fn wait_while_paused(tasks: &TaskMap, uuid: &str, slot: &mut Slot, handle: &Handle) -> bool {
+    loop {
+        match status(tasks, uuid) {
+            Some(CopyMoveTaskStatus::Paused) => {
+                slot.permit = None;
+                thread::sleep(Duration::from_millis(JOB_PAUSE_POLL_MILLIS))
+            }
+            Some(CopyMoveTaskStatus::Cancelled) | None => return false,
+            Some(_) => return handle.block_on(slot.acquire()),
+        }
+    }
+}
+
+// One of the places of the running tasks, held by a task while it isn't paused.
+struct Slot {
+    permits: Arc<Semaphore>,
+    cancel: Arc<Notify>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Slot {
+    // Return false if cancelled while waiting.
+    async fn acquire(&mut self) -> bool {
+        if self.permit.is_none() {
+            self.permit = rocket::tokio::select! {
+                permit = self.permits.clone().acquire_owned() => permit.ok(),
+                _ = self.cancel.notified() => None,
+            };
+        }
+
+        self.permit.is_some()
+    }
+}
+
+fn status(tasks: &TaskMap, uuid: &str) -> Option<CopyMoveTaskStatus> {
+    let tasks = tasks.lock().unwrap();
+    tasks.get(uuid).map(|t| t.status.clone())
+}
+
+fn update<F: FnOnce(&mut CopyMoveTask)>(tasks: &TaskMap, uuid: &str, f: F) {
+    let mut tasks = tasks.lock().unwrap();
+    if let Some(task) = tasks.get_mut(uuid) {
+        f(task);
+    }
+}
+
+async fn save_history(pool: &Pool<Sqlite>, storage: &Path, task: &CopyMoveTask) -> AnyResult<()> {
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+    Job::new(task, storage).insert_query(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
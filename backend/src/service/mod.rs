@@ -1,8 +1,21 @@
+pub mod access;
 pub mod app_state;
+pub mod audit;
 pub mod auth;
+pub mod checksum;
+pub mod client;
+pub mod content_length;
 pub mod fairings;
+pub mod fetch;
+pub mod job;
 pub mod migrate_dir;
+pub mod quota;
 pub mod range;
+pub mod share_password;
 pub mod static_route;
 pub mod token;
+pub mod totp;
 pub mod track;
+pub mod tus;
+pub mod upload_gc;
+pub mod upload_lock;
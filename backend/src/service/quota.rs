@@ -0,0 +1,68 @@
+use crate::entity::{
+    quota::{DirQuota, UploadedFile},
+    user::User,
+};
+use anyhow::Result as AnyResult;
+use rocket::tokio::task;
+use sqlx::{pool::PoolConnection, Sqlite};
+use std::path::Path;
+use walkdir::WalkDir;
+
+// Check whether writing `incoming` bytes into the target dir would exceed the quota of
+// the user or of any directory containing the target.
+// For a move, the bytes already inside a directory are not counted twice,
+// and the user quota is not affected as the files keep their owners.
+pub async fn exceeds_quota(
+    storage: &Path,
+    user_id: i64,
+    target_dir: &Path,
+    incoming: u64,
+    moved_from: Option<&Path>,
+    conn: &mut PoolConnection<Sqlite>,
+) -> AnyResult<bool> {
+    if moved_from.is_none() && user_id > 0 {
+        if let Some(user) = User::find_user_by_id(user_id, conn).await? {
+            let used = UploadedFile::find_user_usage(user_id, conn).await?;
+            if user.quota_bytes > 0 && used as u64 + incoming > user.quota_bytes as u64 {
+                return Ok(true);
+            }
+        }
+    }
+
+    for quota in DirQuota::find_all(conn).await? {
+        let quota_path = storage.join(&quota.path);
+        if !target_dir.starts_with(&quota_path) {
+            continue;
+        }
+
+        if let Some(source) = moved_from {
+            if source.starts_with(&quota_path) {
+                continue;
+            }
+        }
+
+        if dir_size(&quota_path).await? + incoming > quota.max_bytes as u64 {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+// Total size of the files under the path, or of the path itself if it is a file.
+// The walk runs in a blocking thread, as a large dir takes a while.
+pub async fn dir_size(path: &Path) -> AnyResult<u64> {
+    let path = path.to_path_buf();
+    Ok(task::spawn_blocking(move || walk_size(&path)).await?)
+}
+
+fn walk_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|meta| meta.len())
+        .sum()
+}
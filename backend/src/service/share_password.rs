@@ -0,0 +1,31 @@
+use crate::entity::error::Error;
+use crate::entity::request::SharePasswordForm;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+
+// The password of a share, sent in the `X-Share-Password` header rather than the url,
+// which ends up in logs and browser history. Browsers post it in a form instead.
+pub struct SharePassword {
+    pub password: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SharePassword {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let password = req.headers().get_one("X-Share-Password").map(String::from);
+
+        Outcome::Success(SharePassword { password })
+    }
+}
+
+impl From<SharePasswordForm> for SharePassword {
+    fn from(form: SharePasswordForm) -> Self {
+        SharePassword {
+            password: Some(form.password),
+        }
+    }
+}
@@ -20,7 +20,8 @@ pub fn serve() -> Vec<Route> {
         settings,
         profile,
         forgot_password,
-        reset_password
+        reset_password,
+        share
     ]
 }
 
@@ -57,6 +58,12 @@ async fn reset_password(_uuid: String) -> Option<NamedFile> {
     open_index_page().await
 }
 
+// Visitors enter the password of a protected share here.
+#[get("/share/<_slug>")]
+async fn share(_slug: String) -> Option<NamedFile> {
+    open_index_page().await
+}
+
 #[get("/setup")]
 async fn setup(state: &State<AppState>) -> Result<Option<NamedFile>, Error> {
     match state.get_first_run() {
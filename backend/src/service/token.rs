@@ -1,7 +1,10 @@
 use super::app_state::AppState;
 use crate::entity::error::Error;
 use crate::util;
-use crate::util::constants::{ACCESS_TOKEN, ACCESS_TOKEN_MINS, REFRESH_TOKEN, REFRESH_TOKEN_DAYS};
+use crate::util::constants::{
+    ACCESS_TOKEN, ACCESS_TOKEN_MINS, REFRESH_TOKEN, REFRESH_TOKEN_DAYS, TWO_FACTOR_TOKEN,
+    TWO_FACTOR_TOKEN_MINS,
+};
 use anyhow::Result as AnyResult;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use rocket::{
@@ -86,17 +89,20 @@ impl<'r> FromRequest<'r> for AccessToken {
 pub struct RefreshToken {
     pub exp: usize,
     pub uid: i64,
+    // Session id, checked against the SESSION table when refreshing.
+    pub sid: String,
 }
 
 impl Token for RefreshToken {}
 
 impl RefreshToken {
-    pub fn new(uid: i64) -> Self {
+    pub fn new(uid: i64, sid: &str) -> Self {
         let expire_time = util::get_utc_seconds() + REFRESH_TOKEN_DAYS * 24 * 60 * 60;
 
         RefreshToken {
             exp: expire_time as usize,
             uid,
+            sid: sid.to_owned(),
         }
     }
 }
@@ -120,6 +126,46 @@ impl<'r> FromRequest<'r> for RefreshToken {
     }
 }
 
+// Issued after the password is verified, while the second login step is pending.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorToken {
+    pub exp: usize,
+    pub uid: i64,
+}
+
+impl Token for TwoFactorToken {}
+
+impl TwoFactorToken {
+    pub fn new(uid: i64) -> Self {
+        let expire_time = util::get_utc_seconds() + TWO_FACTOR_TOKEN_MINS * 60;
+
+        TwoFactorToken {
+            exp: expire_time as usize,
+            uid,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TwoFactorToken {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(token_str) = req.cookies().get(TWO_FACTOR_TOKEN) {
+            if let Some(state) = req.rocket().state::<AppState>() {
+                if let Ok(secret) = state.get_secret() {
+                    if let Ok(token) = TwoFactorToken::decode(token_str.value(), &secret) {
+                        return Outcome::Success(token);
+                    }
+                }
+            }
+        }
+
+        Outcome::Failure((Status::Unauthorized, Error::Unauthorized))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
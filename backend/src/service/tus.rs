@@ -0,0 +1,141 @@
+use crate::entity::error::Error;
+use crate::util::constants::TUS_VERSION;
+use crate::util::hash;
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::Response;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+// The headers of the tus protocol, see https://tus.io/protocols/resumable-upload.html.
+// The metadata values and the checksum are decoded from base64.
+#[derive(Debug, Default)]
+pub struct TusHeaders {
+    pub upload_length: Option<u64>,
+    pub upload_offset: Option<u64>,
+    pub upload_metadata: HashMap<String, String>,
+    pub upload_checksum: Option<(String, Vec<u8>)>,
+    pub content_type: Option<String>,
+}
+
+// Every tus response carries the protocol version, besides the given headers.
+pub struct TusResponse {
+    pub status: Status,
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl TusResponse {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            headers: vec![],
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TusHeaders {
+    type Error = Error;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let bad_request = (Status::BadRequest, Error::BadRequest);
+        let headers = req.headers();
+        let mut tus = TusHeaders::default();
+
+        if let Some(v) = headers.get_one("Upload-Length") {
+            match v.trim().parse() {
+                Ok(length) => tus.upload_length = Some(length),
+                Err(_) => return Outcome::Failure(bad_request),
+            }
+        }
+
+        if let Some(v) = headers.get_one("Upload-Offset") {
+            match v.trim().parse() {
+                Ok(offset) => tus.upload_offset = Some(offset),
+                Err(_) => return Outcome::Failure(bad_request),
+            }
+        }
+
+        if let Some(v) = headers.get_one("Upload-Metadata") {
+            match parse_metadata(v) {
+                Some(metadata) => tus.upload_metadata = metadata,
+                None => return Outcome::Failure(bad_request),
+            }
+        }
+
+        if let Some(v) = headers.get_one("Upload-Checksum") {
+            match hash::parse_checksum(v) {
+                Some(checksum) => tus.upload_checksum = Some(checksum),
+                None => return Outcome::Failure(bad_request),
+            }
+        }
+
+        tus.content_type = headers.get_one("Content-Type").map(|v| v.to_owned());
+
+        Outcome::Success(tus)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for TusResponse {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let mut builder = Response::build();
+        builder
+            .status(self.status)
+            .raw_header("Tus-Resumable", TUS_VERSION);
+        for (name, value) in self.headers {
+            builder.raw_header(name, value);
+        }
+
+        builder.sized_body(0, Cursor::new(vec![])).ok()
+    }
+}
+
+// Comma separated pairs of a key and an optional base64 encoded value, e.g. "filename YS50eHQ=,private".
+fn parse_metadata(header: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+
+    for pair in header
+        .split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+    {
+        let mut parts = pair.split(' ');
+        let key = parts.next()?;
+        let value = match parts.next() {
+            Some(encoded) => String::from_utf8(base64::decode(encoded).ok()?).ok()?,
+            None => String::new(),
+        };
+
+        if parts.next().is_some() {
+            return None;
+        }
+        metadata.insert(key.to_owned(), value);
+    }
+
+    Some(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata = parse_metadata("filename YS50eHQ=, private").unwrap();
+        assert_eq!(metadata.get("filename").unwrap(), "a.txt");
+        assert_eq!(metadata.get("private").unwrap(), "");
+
+        assert!(parse_metadata("").unwrap().is_empty());
+        assert!(parse_metadata("filename !!!").is_none());
+        assert!(parse_metadata("filename YQ== YQ==").is_none());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::entity::upload_task::UploadTask;
+use crate::util::{self, constants::UPLOAD_GC_INTERVAL_SECS};
+use anyhow::Result as AnyResult;
+use rocket::tokio::{self, fs, time};
+use sqlx::{Acquire, Pool, Sqlite};
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+// Remove the upload tasks not touched for `max_age_secs`, along with their slices,
+// at startup and then periodically.
+pub fn spawn(pool: Pool<Sqlite>, max_age_secs: i64) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(UPLOAD_GC_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = collect(&pool, max_age_secs).await {
+                eprintln!("Error collecting stale uploads: {}", e);
+            }
+        }
+    });
+}
+
+async fn collect(pool: &Pool<Sqlite>, max_age_secs: i64) -> AnyResult<()> {
+    let before = util::get_utc_seconds() - max_age_secs;
+    let mut conn = pool.acquire().await?;
+    let stale_tasks = UploadTask::find_stale(before, &mut conn).await?;
+
+    let mut tx = conn.begin().await?;
+    for task in stale_tasks.iter() {
+        UploadTask::delete_query(&task.uuid, &mut tx).await?;
+    }
+    tx.commit().await?;
+
+    for task in stale_tasks.iter() {
+        let temp_upload_dir = task.temp_dir();
+        if temp_upload_dir.is_dir() {
+            fs::remove_dir_all(temp_upload_dir).await?;
+        }
+    }
+
+    // Slice dirs without a task may be left by a crash or an older version,
+    // and are removed once they are old enough too.
+    let uuids: HashSet<String> = UploadTask::find_all(&mut conn)
+        .await?
+        .into_iter()
+        .map(|t| t.uuid)
+        .collect();
+    let max_age = Duration::from_secs(max_age_secs.max(0) as u64);
+    let mut dir_iterator = fs::read_dir(util::get_temp_path()).await?;
+    while let Some(entry) = dir_iterator.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if uuids.contains(&name) || !entry.file_type().await?.is_dir() {
+            continue;
+        }
+
+        let modified = entry.metadata().await?.modified()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age >= max_age {
+            fs::remove_dir_all(entry.path()).await?;
+        }
+    }
+
+    Ok(())
+}
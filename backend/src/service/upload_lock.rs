@@ -0,0 +1,40 @@
+use rocket::tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// One lock for each upload being written, so the slices or tus patches of an upload are
+// saved and counted one after another. A lock is dropped once nobody holds it.
+#[derive(Debug, Default)]
+pub struct UploadLocks {
+    locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl UploadLocks {
+    pub async fn lock(&self, uuid: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(uuid.to_owned()).or_default().clone()
+        };
+
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upload_locks() {
+        let locks = UploadLocks::default();
+        let guard = locks.lock("a").await;
+        assert!(locks.locks.lock().unwrap()["a"].try_lock().is_err());
+        let other = locks.lock("b").await;
+
+        drop(guard);
+        drop(other);
+        let _guard = locks.lock("a").await;
+        assert_eq!(locks.locks.lock().unwrap().len(), 1);
+    }
+}
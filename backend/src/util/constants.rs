@@ -12,6 +12,13 @@ pub const ACCESS_TOKEN: &'static str = "oa_access";
 pub const ACCESS_TOKEN_MINS: i64 = 20;
 pub const REFRESH_TOKEN: &'static str = "oa_refresh";
 pub const REFRESH_TOKEN_DAYS: i64 = 7;
+pub const TWO_FACTOR_TOKEN: &'static str = "oa_2fa";
+pub const TWO_FACTOR_TOKEN_MINS: i64 = 5;
+pub const LOGIN_FREE_ATTEMPTS: i64 = 5;
+pub const LOGIN_LOCK_BASE_SECS: i64 = 30;
+pub const LOGIN_LOCK_MAX_SECS: i64 = 60 * 60;
+pub const LOGIN_ATTEMPT_WINDOW_SECS: i64 = 24 * 60 * 60;
+pub const API_TOKEN_PREFIX: &'static str = "oa_";
 pub const CACHE_MAX_AGE: i64 = 60 * 60;
 #[allow(dead_code)]
 pub const APP_VERSION_URL_RELEASE: &'static str =
@@ -23,3 +30,20 @@ pub const APP_VERSION_URL_DEBUG: &'static str =
 pub const CACHE_FILE_EXTS: [&'static str; 3] = ["html", "js", "css"];
 pub const DEFAULT_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
 pub const ZIP_BUFFER_SIZE: usize = 65536;
+pub const COMBINE_BUFFER_SIZE: usize = 65536;
+pub const COPY_BUFFER_SIZE: usize = 65536;
+pub const SHARE_SLUG_LENGTH: usize = 10;
+pub const UPLOAD_EXPIRE_HOURS: i64 = 24;
+pub const UPLOAD_GC_INTERVAL_SECS: u64 = 60 * 60;
+pub const FETCH_MAX_RETRIES: u32 = 3;
+pub const FETCH_RETRY_DELAY_SECS: u64 = 1;
+pub const FETCH_CONNECT_TIMEOUT_SECS: u64 = 30;
+pub const FETCH_READ_TIMEOUT_SECS: u64 = 60;
+pub const FETCH_HISTORY_LIMIT: usize = 20;
+pub const JOB_CONCURRENCY: usize = 2;
+pub const JOB_MAX_PER_USER: usize = 20;
+pub const JOB_HISTORY_LIMIT: i64 = 50;
+pub const JOB_PAUSE_POLL_MILLIS: u64 = 200;
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,termination,checksum";
+pub const TUS_CHECKSUM_ALGORITHMS: &str = "md5,sha1,sha256,blake3";
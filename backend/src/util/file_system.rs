@@ -1,10 +1,12 @@
 use anyhow::Result as AnyResult;
+use async_zip::{write::ZipFileWriter, Compression, ZipEntryBuilder};
 use chardetng::EncodingDetector;
 use encoding_rs::Encoding;
 use rocket::tokio::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use sysinfo::{DiskExt, System, SystemExt};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{self, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use walkdir::WalkDir;
 
 pub fn get_system_volumes() -> AnyResult<Vec<String>> {
     let mut sys = System::new_all();
@@ -81,6 +83,43 @@ pub async fn write_text_file(path: &PathBuf, content: &str) -> AnyResult<()> {
     Ok(())
 }
 
+// Entries are named from the dir itself, and only the files passing the filter are included.
+pub async fn zip_dir<W: AsyncWrite + Unpin, F: Fn(&Path) -> bool>(
+    writer: &mut W,
+    path: &PathBuf,
+    filter: F,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = ZipFileWriter::new(writer);
+    let mut it = WalkDir::new(path).into_iter();
+    while let Some(Ok(entry)) = it.next() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        if entry.path().symlink_metadata().is_err() || !filter(entry.path()) {
+            continue;
+        }
+
+        let parent_dir = match path.parent() {
+            Some(p) => p,
+            None => path,
+        };
+        let filename = match entry.path().strip_prefix(parent_dir) {
+            Ok(v) => v.to_str().unwrap(),
+            Err(_) => continue,
+        };
+        let entry_op =
+            ZipEntryBuilder::new(filename.to_owned(), Compression::Stored).unix_permissions(0o644);
+        let mut file = tokio::fs::File::open(entry.path()).await?;
+        let mut file_writer = writer.write_entry_stream(entry_op).await?;
+        io::copy(&mut file, &mut file_writer).await?;
+        file_writer.close().await?;
+    }
+
+    writer.close().await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,188 @@
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "md5" => Some(HashAlgorithm::Md5),
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+}
+
+// The hash declared by the client for a whole file, e.g. "sha256:<hex>" or "blake3:<hex>".
+// A bare 32 digit hex string is the md5 sent by the web client.
+#[derive(Debug, PartialEq)]
+pub struct FileHash {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl FileHash {
+    pub fn parse(hash: &str) -> Option<Self> {
+        let (algorithm, hex) = match hash.split_once(':') {
+            Some((name, hex)) => (HashAlgorithm::from_name(name)?, hex),
+            None if hash.len() == 32 => (HashAlgorithm::Md5, hash),
+            None => return None,
+        };
+
+        Some(FileHash {
+            algorithm,
+            digest: decode_hex(hex)?,
+        })
+    }
+}
+
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.algorithm.as_str())?;
+        for byte in self.digest.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+// An incremental hasher, so large files are never held in memory to be hashed.
+pub enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(h) => h.finalize().to_vec(),
+            Hasher::Sha1(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Blake3(h) => h.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+pub fn digest(algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+// An algorithm and a base64 encoded digest, e.g. "sha1 Kq5sNclPz7QV2+lfQIuc6R7oRu0=",
+// as in the `Upload-Checksum` header of tus.
+pub fn parse_checksum(header: &str) -> Option<(String, Vec<u8>)> {
+    let mut parts = header.trim().split(' ');
+    let algorithm = parts.next()?.to_lowercase();
+    let digest = base64::decode(parts.next()?).ok()?;
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((algorithm, digest))
+}
+
+// Return `None` if the algorithm is not supported.
+pub fn verify_checksum(algorithm: &str, expected: &[u8], data: &[u8]) -> Option<bool> {
+    let algorithm = HashAlgorithm::from_name(algorithm)?;
+
+    Some(digest(algorithm, data) == expected)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_hash() {
+        let hash = FileHash::parse("5d41402abc4b2a76b9719d911017c592").unwrap();
+        assert_eq!(hash.algorithm, HashAlgorithm::Md5);
+        assert_eq!(digest(hash.algorithm, b"hello"), hash.digest);
+
+        let hash = FileHash::parse(
+            "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        )
+        .unwrap();
+        assert_eq!(digest(hash.algorithm, b"hello"), hash.digest);
+
+        let hash = FileHash::parse(
+            "BLAKE3:ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f",
+        )
+        .unwrap();
+        assert_eq!(digest(hash.algorithm, b"hello"), hash.digest);
+
+        assert!(FileHash::parse("crc32:3610a686").is_none());
+        assert!(FileHash::parse("sha256:xyz").is_none());
+        assert!(FileHash::parse("abc").is_none());
+
+        let hash = FileHash {
+            algorithm: HashAlgorithm::Sha256,
+            digest: digest(HashAlgorithm::Sha256, b"hello"),
+        };
+        assert_eq!(FileHash::parse(&hash.to_string()), Some(hash));
+    }
+
+    #[test]
+    fn test_checksum() {
+        let (algorithm, digest) = parse_checksum("sha1 qvTGHdzF6KLavt4PO0gs2a6pQ00=").unwrap();
+        assert_eq!(algorithm, "sha1");
+        assert_eq!(verify_checksum(&algorithm, &digest, b"hello"), Some(true));
+        assert_eq!(verify_checksum(&algorithm, &digest, b"world"), Some(false));
+        assert_eq!(verify_checksum("crc32", &digest, b"hello"), None);
+        assert!(parse_checksum("sha1").is_none());
+    }
+}
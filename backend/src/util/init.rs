@@ -11,15 +11,13 @@ use sqlx::{
 use std::path::PathBuf;
 
 pub async fn init_app() -> AnyResult<()> {
-    // Remove temp dir and create it again at every startup.
+    // Keep the temp dir across restarts, as unfinished uploads can be resumed.
+    // Stale slices are removed by the upload garbage collector.
     let temp_dir = util::get_temp_path();
     if !temp_dir.exists() {
         fs::create_dir_all(&temp_dir).await?;
     } else if temp_dir.is_file() {
         return Err(anyhow::anyhow!("temp dir location occupied as a file"));
-    } else {
-        fs::remove_dir_all(&temp_dir).await?;
-        fs::create_dir_all(&temp_dir).await?;
     }
 
     // Create db and run migration files if db not existed.
@@ -1,5 +1,5 @@
 use crate::util;
-use crate::util::constants::DEFAULT_IP;
+use crate::util::constants::{DEFAULT_IP, JOB_CONCURRENCY, UPLOAD_EXPIRE_HOURS};
 use anyhow::Result as AnyResult;
 use std::cmp::Ordering;
 use std::fs::File;
@@ -13,6 +13,9 @@ pub struct ServerConfig {
     pub port: u16,
     pub certs: Option<String>,
     pub key: Option<String>,
+    pub upload_expire_hours: i64,
+    // The copy and move tasks run at once, while the others are queued.
+    pub job_concurrency: usize,
 }
 
 impl Default for ServerConfig {
@@ -22,6 +25,8 @@ impl Default for ServerConfig {
             port: 8000,
             certs: None,
             key: None,
+            upload_expire_hours: UPLOAD_EXPIRE_HOURS,
+            job_concurrency: JOB_CONCURRENCY,
         }
     }
 }
@@ -51,6 +56,13 @@ impl ServerConfig {
                         "port" => server.port = parts[1].trim().parse()?,
                         "certs" => server.certs = Some(parts[1].trim().to_string()),
                         "key" => server.key = Some(parts[1].trim().to_string()),
+                        "upload_expire_hours" => {
+                            server.upload_expire_hours = parts[1].trim().parse()?;
+                            if server.upload_expire_hours <= 0 {
+                                return Err(malform);
+                            }
+                        }
+                        "job_concurrency" => server.job_concurrency = parts[1].trim().parse()?,
                         _ => return Err(malform),
                     }
                 }
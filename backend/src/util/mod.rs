@@ -1,6 +1,7 @@
 pub mod constants;
 pub mod db;
 pub mod file_system;
+pub mod hash;
 pub mod init;
 pub mod local_ip;
 pub mod rocket_env;
@@ -23,6 +24,11 @@ pub fn parse_encoded_url(url: &str) -> AnyResult<PathBuf> {
     Ok(PathBuf::from(url_decode.into_owned()))
 }
 
+// A name for a new file or dir must not contain separators or refer to the parent.
+pub fn is_valid_filename(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains('\\')
+}
+
 pub fn get_frontend_path() -> PathBuf {
     let path = get_frontend_dir();
 
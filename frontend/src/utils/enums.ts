@@ -75,4 +75,13 @@ export enum ECopyMoveTaskStatus {
   InProgress = "InProgress",
   Success = "Success",
   Failed = "Failed",
+  Paused = "Paused",
+  Cancelled = "Cancelled",
+}
+
+export enum EConflictPolicy {
+  fail = "fail",
+  overwrite = "overwrite",
+  rename = "rename",
+  skip = "skip",
 }
@@ -110,6 +110,9 @@ export interface IUploadTask {
   status: enums.EUploadStatus;
   progress: number;
   hash?: string;
+  conflict: enums.EConflictPolicy;
+  relativeDir?: string; // the dir of the file inside an uploaded folder
+  batchId?: string; // shared by the files of an uploaded folder
 }
 
 export interface IUploadRequest {
@@ -117,6 +120,14 @@ export interface IUploadRequest {
   size: number;
   target: string; // target directory to store the uploading file
   hash: string; // the md5 value of the file
+  conflict?: enums.EConflictPolicy;
+  relative_dir?: string;
+  batch_id?: string;
+}
+
+export interface IUploadResult {
+  filename: string;
+  skipped: boolean;
 }
 
 export interface IMousePosition {
@@ -138,6 +149,21 @@ export interface ICopyMoveTask {
   target: string;
   progress: number;
   is_copy: boolean;
+  error: string;
+  created_at: number;
+  finished_at: number;
+}
+
+export interface IShare {
+  share_id: number;
+  slug: string;
+  path: string;
+  user_id: number;
+  has_password: boolean;
+  max_downloads: number;
+  download_count: number;
+  created_at: number;
+  expire_at: number;
 }
 
 export type ContextMenuAction =
@@ -1,5 +1,5 @@
-import type { IFile, IUploadRequest, IUploadTask } from "./types";
-import { EUploadStatus } from './enums';
+import type { IFile, IUploadRequest, IUploadResult, IUploadTask } from "./types";
+import { EFileType, EUploadStatus } from './enums';
 import * as api from './api';
 import { updateTask, pushWorker, removeWorker, terminateWorkers, pushFile } from "./store";
 import { inferFileType } from "./util";
@@ -32,6 +32,9 @@ function buildUploadRequest(task: IUploadTask) {
     size: task.file.size,
     target: encodeURIComponent(task.targetDir.join("/")),
     hash: task.hash,
+    conflict: task.conflict,
+    relative_dir: task.relativeDir,
+    batch_id: task.batchId,
   };
 
   return payload;
@@ -88,14 +91,17 @@ async function uploadFile(task: IUploadTask, hash: string) {
 
 async function finishUpload(task: IUploadTask) {
   try {
-    await api.post(`/api/finish-upload/${task.uuid}`, null, false);
+    const result: IUploadResult = await api.post(`/api/finish-upload/${task.uuid}`, null, true);
     updateTask(task.file, EUploadStatus.success, task.progress);
+    if (result.skipped) return;
 
+    // The file of a folder upload shows up as the top folder in the current dir.
+    const topDir = task.relativeDir ? task.relativeDir.split("/")[0] : "";
     const newFile: IFile = {
       dir: task.targetDir.join("/") || "/",
-      file_type: inferFileType(task.file.name),
-      size: task.file.size,
-      filename: task.file.name,
+      file_type: topDir ? EFileType.Dir : inferFileType(result.filename),
+      size: topDir ? 0 : task.file.size,
+      filename: topDir || result.filename,
       least_permission: 0
     };
 
@@ -106,7 +112,8 @@ async function finishUpload(task: IUploadTask) {
   }
 }
 
-export async function cancelUploads(tasks: Array<IUploadTask>) {
+// Cancelling the batches also removes the tasks of a folder which are not known here.
+export async function cancelUploads(tasks: Array<IUploadTask>, cancelBatches = false) {
   const tasksToRemove = tasks.filter(t => !!t.uuid);
   if (tasksToRemove.filter(t => t.status === EUploadStatus.preparing ||
     t.status === EUploadStatus.uploading ||
@@ -115,9 +122,12 @@ export async function cancelUploads(tasks: Array<IUploadTask>) {
   }
 
   const uuids = tasksToRemove.map((t) => t.uuid);
+  const batch_ids = cancelBatches
+    ? [...new Set(tasks.filter(t => !!t.batchId).map(t => t.batchId))]
+    : [];
 
   try {
-    const payload = { uuids };
+    const payload = { uuids, batch_ids };
     await api.post(`/api/cancel-upload`, payload, false);
   } catch (e) {
     throw e;